@@ -0,0 +1,359 @@
+use std::collections::HashMap;
+
+use crate::constants;
+use crate::diagnostic::{Diagnostic, Span};
+use crate::error::TranslateError;
+
+/// Symbols every Hack program starts with
+const PREDEFINED_SYMBOLS: [(&str, u16); 23] = [
+    ("SP", 0), ("LCL", 1), ("ARG", 2), ("THIS", 3), ("THAT", 4),
+    ("R0", 0), ("R1", 1), ("R2", 2), ("R3", 3), ("R4", 4), ("R5", 5), ("R6", 6), ("R7", 7),
+    ("R8", 8), ("R9", 9), ("R10", 10), ("R11", 11), ("R12", 12), ("R13", 13), ("R14", 14), ("R15", 15),
+    ("SCREEN", 16384), ("KBD", 24576)
+];
+
+/// Machine code produced by the assembler
+pub struct MachineCode {
+    /// One 16-bit instruction per ROM address
+    pub instructions: Vec<u16>,
+    /// Address of every label and variable, including the predefined symbols
+    pub symbols: HashMap<String, u16>
+}
+
+impl MachineCode {
+    /// Machine code in the .hack text format, one 16 character binary instruction per line
+    pub fn to_hack_string(&self) -> String {
+        let mut hack = String::new();
+
+        for instruction in self.instructions.iter() {
+            hack.push_str(&format!("{:016b}\n", instruction));
+        }
+
+        return hack;
+    }
+}
+
+/// Line of assembly without its comment or surrounding whitespace
+pub fn strip_comment(line: &str) -> &str {
+    let code = match line.find("//") {
+        Some(comment_index) => &line[0..comment_index],
+        None                => line
+    };
+
+    return code.trim();
+}
+
+/// Checks if a line of assembly is a real instruction that takes up a ROM address
+/// 
+/// Comments, blank lines and (LABEL) pseudo instructions are not
+pub fn is_instruction(line: &str) -> bool {
+    let code = strip_comment(line);
+
+    return !code.is_empty() && !code.starts_with('(');
+}
+
+/// A single instruction once comments and whitespace are removed
+enum AsmLine {
+    /// @value or @symbol
+    AInstruction(String),
+    /// dest=comp;jump
+    CInstruction(String)
+}
+
+/// Assembles Hack assembly into machine code
+/// 
+/// name is used as the file in the span of any errors, lines are numbered from 1
+pub fn assemble<S: AsRef<str>>(name: &str, asm: &[S]) -> Result<MachineCode, Vec<Diagnostic>> {
+    let mut diagnostics = Vec::new();
+    let mut lines = Vec::new();
+
+    let mut symbols: HashMap<String, u16> = PREDEFINED_SYMBOLS.iter()
+        .map(|(symbol, addr)| (symbol.to_string(), *addr))
+        .collect();
+
+    // First pass reads every line and records the ROM address of each label
+    let mut rom_addr: usize = 0;
+    let mut overflow_span = None;
+
+    for (line_i, line) in asm.iter().enumerate() {
+        let span = Span::new(name, line_i + 1, 1, line.as_ref().chars().count() + 1);
+
+        let code = match line.as_ref().find("//") {
+            Some(comment_index) => &line.as_ref()[0..comment_index],
+            None                => line.as_ref()
+        };
+        let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+
+        if code.is_empty() {
+            continue;
+        }
+
+        if let Some(label) = code.strip_prefix('(') {
+            let label = match label.strip_suffix(')') {
+                Some(label) if is_symbol(label) => label,
+                _ => {
+                    diagnostics.push(invalid(span, &code, "malformed label"));
+                    continue;
+                }
+            };
+
+            if symbols.insert(label.to_owned(), rom_addr as u16).is_some() {
+                diagnostics.push(Diagnostic::error(Some(span.clone()), TranslateError::DuplicateLabel(label.to_owned())));
+            }
+        }
+        else {
+            // Point at the first instruction that doesn't fit
+            if rom_addr == constants::ROM_SIZE {
+                overflow_span = Some(span.clone());
+            }
+
+            match code.strip_prefix('@') {
+                Some(value) => lines.push((span, AsmLine::AInstruction(value.to_owned()))),
+                None        => lines.push((span, AsmLine::CInstruction(code)))
+            }
+            rom_addr += 1;
+        }
+    }
+
+    if overflow_span.is_some() {
+        return Err(vec![Diagnostic::error(overflow_span, TranslateError::RomOverflow {
+            size: rom_addr,
+            max: constants::ROM_SIZE,
+            largest: Vec::new()
+        })]);
+    }
+
+    // Second pass encodes the instructions, giving new variables RAM addresses from 16 up
+    let mut next_variable = constants::VARIABLE_START;
+    let mut instructions = Vec::new();
+
+    for (span, line) in lines {
+        match line {
+            AsmLine::AInstruction(value) => {
+                if value.starts_with(|c: char| c.is_ascii_digit()) {
+                    match value.parse::<u16>() {
+                        Ok(constant) if constant <= constants::MAX_CONSTANT => instructions.push(constant),
+                        _ => diagnostics.push(invalid(span, &format!("@{}", value), "constant must be 0 to 32767"))
+                    }
+                }
+                else if is_symbol(&value) {
+                    let addr = *symbols.entry(value).or_insert_with(|| {
+                        next_variable += 1;
+                        next_variable - 1
+                    });
+                    instructions.push(addr);
+                }
+                else {
+                    diagnostics.push(invalid(span, &format!("@{}", value), "malformed symbol"));
+                }
+            },
+            AsmLine::CInstruction(code) => {
+                match encode_c_instruction(&code) {
+                    Ok(instruction) => instructions.push(instruction),
+                    Err(reason) => diagnostics.push(invalid(span, &code, reason))
+                }
+            }
+        }
+    }
+
+    if !diagnostics.is_empty() {
+        // Label errors come from the first pass, so put them back in line order
+        diagnostics.sort_by(|a, b| a.span.cmp(&b.span));
+        return Err(diagnostics);
+    }
+
+    return Ok(MachineCode { instructions, symbols });
+}
+
+/// Creates an error for a malformed instruction
+fn invalid(span: Span, instruction: &str, reason: &str) -> Diagnostic {
+    return Diagnostic::error(Some(span), TranslateError::InvalidInstruction {
+        instruction: instruction.to_owned(),
+        reason: reason.to_owned()
+    });
+}
+
+/// Checks if the string is a valid symbol (letters, digits, _ . $ : not starting with a digit)
+pub fn is_symbol(symbol: &str) -> bool {
+    return !symbol.is_empty()
+        && !symbol.starts_with(|c: char| c.is_ascii_digit())
+        && symbol.chars().all(|c| c.is_ascii_alphanumeric() || "_.$:".contains(c));
+}
+
+/// Encodes a dest=comp;jump instruction
+fn encode_c_instruction(code: &str) -> Result<u16, &'static str> {
+    let (dest, rest) = match code.split_once('=') {
+        Some((dest, rest))  => (dest, rest),
+        None                => ("", code)
+    };
+    let (comp, jump) = match rest.split_once(';') {
+        Some((comp, jump))  => (comp, jump),
+        None                => (rest, "")
+    };
+
+    let comp_bits = encode_comp(comp).ok_or("unknown computation")?;
+    let dest_bits = encode_dest(dest).ok_or("unknown destination")?;
+    let jump_bits = encode_jump(jump).ok_or("unknown jump")?;
+
+    return Ok(0b111 << 13 | comp_bits << 6 | dest_bits << 3 | jump_bits);
+}
+
+/// Encodes the a bit and the six c bits of a computation
+/// 
+/// Operands of +, & and | can be given in either order (ex. M+D or D+M)
+fn encode_comp(comp: &str) -> Option<u16> {
+    // M computations are the A computations with the a bit set
+    let (a_bit, a_comp) = if comp.contains('M') {
+        (1, comp.replace('M', "A"))
+    }
+    else {
+        (0, comp.to_owned())
+    };
+
+    let c_bits = comp_bits(&a_comp).or_else(|| {
+        let (op_i, op) = a_comp.char_indices().skip(1).find(|(_, c)| "+&|".contains(*c))?;
+        let swapped = format!("{}{}{}", &a_comp[op_i + 1..], op, &a_comp[0..op_i]);
+        comp_bits(&swapped)
+    })?;
+
+    return Some(a_bit << 6 | c_bits);
+}
+
+/// Six c bits for each computation on A and D
+fn comp_bits(comp: &str) -> Option<u16> {
+    match comp {
+        "0"     => Some(0b101010),
+        "1"     => Some(0b111111),
+        "-1"    => Some(0b111010),
+        "D"     => Some(0b001100),
+        "A"     => Some(0b110000),
+        "!D"    => Some(0b001101),
+        "!A"    => Some(0b110001),
+        "-D"    => Some(0b001111),
+        "-A"    => Some(0b110011),
+        "D+1"   => Some(0b011111),
+        "A+1"   => Some(0b110111),
+        "D-1"   => Some(0b001110),
+        "A-1"   => Some(0b110010),
+        "D+A"   => Some(0b000010),
+        "D-A"   => Some(0b010011),
+        "A-D"   => Some(0b000111),
+        "D&A"   => Some(0b000000),
+        "D|A"   => Some(0b010101),
+        _       => None
+    }
+}
+
+/// Encodes the destination registers (any order of A, D and M, each at most once)
+fn encode_dest(dest: &str) -> Option<u16> {
+    let mut bits = 0;
+
+    for register in dest.chars() {
+        let bit = match register {
+            'A' => 0b100,
+            'D' => 0b010,
+            'M' => 0b001,
+            _   => return None
+        };
+
+        if bits & bit != 0 {
+            return None;
+        }
+        bits |= bit;
+    }
+
+    return Some(bits);
+}
+
+/// Encodes the jump condition
+fn encode_jump(jump: &str) -> Option<u16> {
+    match jump {
+        ""      => Some(0b000),
+        "JGT"   => Some(0b001),
+        "JEQ"   => Some(0b010),
+        "JGE"   => Some(0b011),
+        "JLT"   => Some(0b100),
+        "JNE"   => Some(0b101),
+        "JLE"   => Some(0b110),
+        "JMP"   => Some(0b111),
+        _       => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Assembles the lines and returns the line and reason of every error, in the order reported
+    fn errors(asm: &[&str]) -> Vec<(usize, String)> {
+        return assemble("Test.asm", asm).err().unwrap().iter().map(|diagnostic| {
+            let reason = match &diagnostic.error {
+                TranslateError::InvalidInstruction { reason, .. } => reason.clone(),
+                error => error.to_string()
+            };
+            (diagnostic.span.as_ref().unwrap().line, reason)
+        }).collect();
+    }
+
+    #[test]
+    fn encodes_instructions() {
+        let machine_code = assemble("Test.asm", &[
+            "@2", "D=A", "@3", "D=D+A", "@0", "M=D", "AM=M-1", "0;JMP", "D;JGT", "AMD=!M;JLE"
+        ]).unwrap();
+
+        assert_eq!(machine_code.to_hack_string(), "\
+            0000000000000010\n\
+            1110110000010000\n\
+            0000000000000011\n\
+            1110000010010000\n\
+            0000000000000000\n\
+            1110001100001000\n\
+            1111110010101000\n\
+            1110101010000111\n\
+            1110001100000001\n\
+            1111110001111110\n");
+    }
+
+    #[test]
+    fn swaps_operands() {
+        assert_eq!(encode_c_instruction("M=M+D"), encode_c_instruction("M=D+M"));
+        assert_eq!(encode_c_instruction("D=A&D"), encode_c_instruction("D=D&A"));
+        assert_eq!(encode_c_instruction("D=M|D"), encode_c_instruction("D=D|M"));
+        assert_eq!(encode_c_instruction("MD=D"), encode_c_instruction("DM=D"));
+        // Subtraction isn't commutative
+        assert_ne!(encode_c_instruction("D=A-D"), encode_c_instruction("D=D-A"));
+    }
+
+    #[test]
+    fn allocates_variables_from_16() {
+        let machine_code = assemble("Test.asm", &[
+            "@foo", "(LOOP)", "@bar", "@foo", "@LOOP", "@R13", "@SCREEN"
+        ]).unwrap();
+
+        assert_eq!(machine_code.instructions, vec![16, 17, 16, 1, 13, 16384]);
+        assert_eq!(machine_code.symbols["LOOP"], 1);
+    }
+
+    #[test]
+    fn ignores_comments_and_whitespace() {
+        let machine_code = assemble("Test.asm", &["// comment", "", "  @ 5 // five", " D = A ; JMP"]).unwrap();
+
+        assert_eq!(machine_code.instructions, vec![5, 0b1110110000010111]);
+    }
+
+    #[test]
+    fn reports_malformed_instructions_in_line_order() {
+        assert_eq!(errors(&[
+            "@40000", "D=X", "AA=D", "D;JXX", "@a-b", "(1bad)", "(LOOP)", "(LOOP)", "(END"
+        ]), vec![
+            (1, "constant must be 0 to 32767".to_owned()),
+            (2, "unknown computation".to_owned()),
+            (3, "unknown destination".to_owned()),
+            (4, "unknown jump".to_owned()),
+            (5, "malformed symbol".to_owned()),
+            (6, "malformed label".to_owned()),
+            (8, TranslateError::DuplicateLabel("LOOP".to_owned()).to_string()),
+            (9, "malformed label".to_owned())
+        ]);
+    }
+}
@@ -0,0 +1,796 @@
+use std::collections::HashMap;
+
+use enumset::EnumSet;
+
+use crate::constants;
+use crate::error::TranslateError;
+use crate::instruction::VmInstruction;
+use crate::operations::{Operation, Segment};
+
+/// Generates Hack assembly for VM instructions
+pub struct CodeGen {
+    /// Number of instructions generated so far, used to make labels unique
+    command_count: u32,
+    /// Name of the program being executed (used by static and stuff)
+    program_name: String,
+    /// Function the instructions being generated belong to
+    current_function: Option<String>,
+    /// Number of labels made so far by each prefix (ex. Main.main$ret), used to make them unique
+    label_counts: HashMap<String, u32>,
+    /// Whether to jump to routines shared between commands instead of inlining their code
+    share_code: bool,
+    /// Comparisons that have jumped to their shared routine
+    shared_comparisons: EnumSet<Operation>,
+    /// Calls and returns that have jumped to their shared routine
+    shared_functions: EnumSet<Operation>,
+    /// Resulting command strings (assembly commands) for the instruction being generated
+    parsed_cmd: Vec<String>
+}
+
+impl Default for CodeGen {
+    fn default() -> Self {
+        return CodeGen::new();
+    }
+}
+
+impl CodeGen {
+    pub fn new() -> Self {
+        return CodeGen {
+            command_count: 0,
+            program_name: String::new(),
+            current_function: None,
+            label_counts: HashMap::new(),
+            share_code: false,
+            shared_comparisons: EnumSet::new(),
+            shared_functions: EnumSet::new(),
+            parsed_cmd: Vec::new()
+        };
+    }
+
+    /// Sets the name of the module being generated, used by the static segment
+    /// 
+    /// Starting a new module leaves the scope of the last function
+    pub fn set_program_name(&mut self, prgm_name: &str) {
+        if self.program_name != prgm_name {
+            self.program_name = prgm_name.to_owned();
+            self.current_function = None;
+        }
+    }
+
+    /// Sets whether commands jump to shared routines instead of inlining their code
+    /// 
+    /// The routines have to be written with write_runtime once every command is generated
+    pub fn set_share_code(&mut self, share_code: bool) {
+        self.share_code = share_code;
+    }
+
+    /// Generates the routines commands have jumped to, placed at the start of the program
+    /// 
+    /// Empty if no command used a shared routine
+    pub fn write_runtime(&mut self) -> Vec<String> {
+        self.parsed_cmd.clear();
+
+        if self.shared_comparisons.is_empty() && self.shared_functions.is_empty() {
+            return Vec::new();
+        }
+
+        // Execution starts at the top, so skip over the routines
+        self.jump_to_label(constants::RUNTIME_START);
+
+        for operation in self.shared_comparisons.iter() {
+            self.write_comparison_routine(operation);
+        }
+
+        if self.shared_functions.contains(Operation::Call) {
+            self.append_label(constants::CALL_ROUTINE);
+            self.write_call_routine();
+        }
+
+        if self.shared_functions.contains(Operation::Return) {
+            self.append_label(constants::RETURN_ROUTINE);
+            self.return_func();
+        }
+
+        self.append_label(constants::RUNTIME_START);
+
+        return std::mem::take(&mut self.parsed_cmd);
+    }
+
+    /// Generates the init commands which set up the stack pointer
+    pub fn write_init(&mut self, initial_sp: u16) -> Vec<String> {
+        self.parsed_cmd.clear();
+
+        self.parsed_cmd.extend([
+            format!("@{}", initial_sp),
+            "D=A".to_string(),
+            "@SP".to_string(),
+            "M=D".to_string(),
+        ]);
+
+        self.command_count += 1;
+
+        return std::mem::take(&mut self.parsed_cmd);
+    }
+
+    /// Generates the hack commands for a single instruction
+    /// 
+    /// Fails without generating anything if the instruction can't be translated (ex. popping into
+    /// the constant segment)
+    pub fn generate(&mut self, instruction: &VmInstruction) -> Result<Vec<String>, TranslateError> {
+        instruction.validate()?;
+
+        self.parsed_cmd.clear();
+
+        match instruction {
+            VmInstruction::Arithmetic(op)           => self.gen_arithmetic(*op),
+            VmInstruction::Push { segment, index }  => self.exec_memory(Operation::Push, *segment, *index),
+            VmInstruction::Pop { segment, index }   => self.exec_memory(Operation::Pop, *segment, *index),
+            VmInstruction::Label(label)             => {
+                let label = self.mangle_label(label);
+                self.append_label(&label);
+            },
+            VmInstruction::Goto(label)              => {
+                let label = self.mangle_label(label);
+                self.branch_addr(&label, None);
+            },
+            VmInstruction::IfGoto(label)            => {
+                let label = self.mangle_label(label);
+                self.pop_d();
+                
+                self.branch_addr(&label, Some("JNE"));
+            },
+            VmInstruction::Function { name, nlocals } => {
+                self.current_function = Some(name.clone());
+                self.function_func(name, *nlocals);
+            },
+            VmInstruction::Call { name, nargs } if self.share_code => self.call_shared(name, *nargs),
+            VmInstruction::Call { name, nargs }     => self.call_func(name, *nargs),
+            VmInstruction::Return if self.share_code => {
+                self.shared_functions.insert(Operation::Return);
+                self.jump_to_label(constants::RETURN_ROUTINE);
+            },
+            VmInstruction::Return                   => self.return_func()
+        }
+
+        self.command_count += 1;
+
+        return Ok(std::mem::take(&mut self.parsed_cmd));
+    }
+
+    /// Scopes a branching label to the function it appears in
+    /// 
+    /// Ex:
+    ///     "LOOP" in function Main.main
+    ///         -> "Main.main$LOOP"
+    /// 
+    /// Labels outside of any function are scoped to the module instead
+    fn mangle_label(&self, label: &str) -> String {
+        return format!("{}${}", self.scope(), label);
+    }
+
+    /// Name of the function being generated, or the module outside of any function
+    fn scope(&self) -> &str {
+        return self.current_function.as_deref().unwrap_or(&self.program_name);
+    }
+
+    /// Creates the next return address label for a call from the current function
+    /// 
+    /// Ex:
+    ///     third call in Main.main
+    ///         -> "Main.main$ret.2"
+    fn next_return_label(&mut self) -> String {
+        return self.next_numbered_label("ret");
+    }
+
+    /// Creates the next label for a comparison in the current function to return to from its
+    /// shared routine, numbered apart from the calls
+    /// 
+    /// Ex:
+    ///     first comparison in Main.main
+    ///         -> "Main.main$cmp.0"
+    fn next_comparison_label(&mut self) -> String {
+        return self.next_numbered_label("cmp");
+    }
+
+    /// Creates the next label of a kind in the current function
+    fn next_numbered_label(&mut self, kind: &str) -> String {
+        let prefix = format!("{}${}", self.scope(), kind);
+        let count = self.label_counts.entry(prefix.clone()).or_insert(0);
+        let label = format!("{}.{}", prefix, count);
+        *count += 1;
+
+        return label;
+    }
+
+    /// Adds an assembly line command to the command vector for this VM command
+    fn append_cmd(&mut self, cmd: &str) {
+        self.parsed_cmd.push(cmd.to_owned());
+    }
+
+    /// Goes to specified address in the RAM with condition (None => "JMP")
+    /// 
+    /// Ex:
+    ///     goto_addr("TEST_LABEL", "JLT") 
+    ///      |
+    ///      |
+    ///      V
+    ///     @TEST_LABEL
+    ///     0; JLT
+    fn branch_addr(&mut self, addr: &str, jmp_cmd: Option<&str>) {
+        self.set_addr(addr);
+
+        let jmp = jmp_cmd.unwrap_or("JMP");
+
+        self.append_cmd(&format!("D; {}", jmp));
+    }
+
+    /// Sets address to the given string
+    fn set_addr(&mut self, addr: &str) {
+        self.append_cmd(&format!("@{}", addr));
+    }
+
+    /// Appends the label to the command
+    /// 
+    /// Ex:
+    ///     label
+    ///         -> "({label})"
+    fn append_label(&mut self, label: &str) {
+        self.append_cmd(format!("({})", label).as_str());
+    }
+
+    /// Jumps to the given label such in the goto command
+    fn jump_to_label(&mut self, label: &str) {
+        self.set_addr(label);
+        self.append_cmd("0; JMP");
+    }
+
+    /// Pushes the label's address onto the stack
+    fn push_label_addr(&mut self, label: &str) {
+        self.set_addr(label);
+        self.append_cmd("D=A");
+        self.push_d();
+    }
+
+    /// Pushes the address of the passed in segment to the stack
+    fn push_segment_label(&mut self, segment: Segment) {
+        self.set_addr(segment.to_string().as_str());
+        self.append_cmd("D=M");
+        self.push_d();
+    }
+
+    /// Calls a function
+    /// 
+    /// Saves the current stack and return address
+    fn call_func(&mut self, function_name: &str, nargs: u32) {
+        // Push return address
+        let return_addr = self.next_return_label();
+        self.push_label_addr(return_addr.as_str());
+
+        // Push LCL
+        self.push_segment_label(Segment::Local);
+
+        // Push ARG
+        self.push_segment_label(Segment::Argument);
+
+        // Push THIS
+        self.push_segment_label(Segment::This);
+
+        // Push THAT
+        self.push_segment_label(Segment::That);
+
+        // ARG = SP - 5 - nArgs
+        self.set_d(5);
+        self.set_addr(nargs.to_string().as_str());
+        self.append_cmd("D=D+A");
+        self.set_addr("SP");
+        self.append_cmd("D=M-D");
+        self.set_addr(Segment::Argument.to_string().as_str());
+        self.append_cmd("M=D");
+
+        // LCL = SP
+        self.set_addr("SP");
+        self.append_cmd("D=M");
+        self.set_addr(Segment::Local.to_string().as_str());
+        self.append_cmd("M=D");
+
+        // Goto functionName
+        self.jump_to_label(function_name);
+
+        // (returnAddress)
+        self.append_label(return_addr.as_str());
+
+    }
+
+    /// Calls a function through the shared call routine
+    /// 
+    /// The function's address goes in R13, nargs in R14 and the return address in D
+    fn call_shared(&mut self, function_name: &str, nargs: u32) {
+        self.shared_functions.insert(Operation::Call);
+        let return_addr = self.next_return_label();
+
+        // 0 and 1 can be set directly
+        if nargs <= 1 {
+            self.set_addr("R14");
+            self.append_cmd(&format!("M={}", nargs));
+        }
+        else {
+            self.set_d(nargs);
+            self.set_addr("R14");
+            self.append_cmd("M=D");
+        }
+
+        self.set_addr(function_name);
+        self.append_cmd("D=A");
+        self.set_addr("R13");
+        self.append_cmd("M=D");
+
+        self.set_addr(&return_addr);
+        self.append_cmd("D=A");
+        self.jump_to_label(constants::CALL_ROUTINE);
+
+        self.append_label(&return_addr);
+    }
+
+    /// Writes the shared call routine, which does the work of call_func with the function's
+    /// address in R13, nargs in R14 and the return address in D
+    fn write_call_routine(&mut self) {
+        // Push return address
+        self.push_d();
+
+        // Push LCL, ARG, THIS, THAT
+        self.push_segment_label(Segment::Local);
+        self.push_segment_label(Segment::Argument);
+        self.push_segment_label(Segment::This);
+        self.push_segment_label(Segment::That);
+
+        // ARG = SP - 5 - nArgs
+        self.set_addr("R14");
+        self.append_cmd("D=M");
+        self.set_addr("5");
+        self.append_cmd("D=D+A");
+        self.set_addr("SP");
+        self.append_cmd("D=M-D");
+        self.set_addr(Segment::Argument.to_string().as_str());
+        self.append_cmd("M=D");
+
+        // LCL = SP
+        self.set_addr("SP");
+        self.append_cmd("D=M");
+        self.set_addr(Segment::Local.to_string().as_str());
+        self.append_cmd("M=D");
+
+        // Goto the function
+        self.set_addr("R13");
+        self.append_cmd("A=M");
+        self.append_cmd("0; JMP");
+    }
+
+    /// Creates a function
+    /// 
+    /// Builds the new stack frame
+    fn function_func(&mut self, function_name: &str, nlocal: u32) {
+        // (functionName)
+        self.append_label(function_name);
+
+        // push 0 * nlocal
+        for _i in 0..nlocal {
+            self.set_d(0);
+            self.push_d();
+        }
+
+    }
+
+    /// Sets the address of the given segment to the value at new_addr + offset
+    fn set_segment(&mut self, segment: Segment, new_addr: &str, offset: i32) {
+        self.set_d(offset.unsigned_abs());
+
+        self.set_addr(new_addr);
+
+        if offset < 0 {
+            self.append_cmd("A=M-D");
+        }
+        else {
+            self.append_cmd("A=M+D");
+        }
+
+        // We now save this value into the segment
+        self.append_cmd("D=M");
+
+        self.set_addr(segment.to_string().as_str());
+        self.append_cmd("M=D");
+
+    }
+
+    /// Returns the function back to previous address
+    /// 
+    /// Cuts down current stack frame and jumps to return address on the stack
+    fn return_func(&mut self) {
+        // endFrame = LCL
+        self.set_addr(Segment::Local.to_string().as_str());
+        self.append_cmd("D=M");
+        self.set_addr("R13"); // Save LCL as endFrame in R13
+        self.append_cmd("M=D");
+
+        // retAddr = *(endFrame - 5)
+        self.set_d(5);
+        self.set_addr("R13");
+        self.append_cmd("A=M-D");
+        self.append_cmd("D=M");
+        self.set_addr("R14"); // Save return address in R14
+        self.append_cmd("M=D");
+
+        // *ARG = pop()
+        self.pop_d();
+        self.set_addr(Segment::Argument.to_string().as_str());
+        self.append_cmd("A=M");
+        self.append_cmd("M=D");
+
+        // SP = ARG + 1
+        self.set_addr(Segment::Argument.to_string().as_str());
+        self.append_cmd("D=M");
+        self.set_addr("SP");
+        self.append_cmd("M=D+1");
+
+        // THAT = *(endFame - 1)
+        self.set_segment(Segment::That, "R13", -1);
+
+        // THIS = *(endFame - 2)
+        self.set_segment(Segment::This, "R13", -2);
+
+        // ARG = *(endFame - 3)
+        self.set_segment(Segment::Argument, "R13", -3);
+        
+        // LCL = *(endFame - 4)
+        self.set_segment(Segment::Local, "R13", -4);
+
+        // goto retAddr
+        self.set_addr("R14");
+        self.append_cmd("A=M");
+        self.append_cmd("0;JMP");
+
+    }
+
+    /// Set d register to value i
+    fn set_d(&mut self, i: u32) {
+        self.set_addr(i.to_string().as_str());
+        self.append_cmd("D=A");
+    }
+
+    /// Push whatever is in d onto the stack
+    fn push_d(&mut self) {
+        self.append_cmd("@SP");
+        self.append_cmd("M=M+1");
+        self.append_cmd("A=M-1");
+        self.append_cmd("M=D");
+    }
+
+    /// Pop whatever is on the stack to d
+    fn pop_d(&mut self) {
+        self.append_cmd("@SP");
+        self.append_cmd("AM=M-1");
+        self.append_cmd("D=M");
+    }
+
+    /// Save SP1 in current
+    fn get_sp1(&mut self) {
+        self.append_cmd("@SP");     // Go to stack pointer
+        self.append_cmd("AM=M-1");  // Get pointer at SP - 1 and goto address
+    }
+
+    /// Save SP1 in D and locate at SP2
+    fn get_sp2(&mut self) {
+        self.append_cmd("D=M");     // Save the value at the address in D reg
+        self.append_cmd("@SP");     // Go back to the stack pointer
+        // ISSUE: This may cause issues if M is saved after A
+        self.append_cmd("AM=M-1");  // Go back to SP - 1 (second val) and jump to value
+        // Next line will be something like M=M+D depending on operation
+    }
+
+    /// Increment the stack pointer
+    fn inc_sp(&mut self) {
+        // Increment SP
+        self.append_cmd("@SP");
+        self.append_cmd("M=M+1");
+    }
+
+    /// Run this function after loading operating value into D and decider as a param
+    ///
+    /// Example
+    ///     jump_ins = "JEQ"
+    /// 
+    /// Now this function will write the behavior that if D is EQ 0 it will
+    /// load true val on the stack, otherwise false val
+    fn load_bool_jumps(&mut self, jump_ins: &str) {
+        let true_label = "RESULT_TRUE";
+        let false_label = "RESULT_FALSE";
+        let set_val_label = "RESULT_SET";
+
+        self.append_cmd(&format!("@{}_{}", true_label, self.command_count)); // Set the true label
+        self.append_cmd(&format!("D;{}", jump_ins)); // Jump if D is 0
+        self.append_cmd(&format!("@{}_{}", false_label, self.command_count)); // Set false label
+        self.append_cmd("0;JMP"); // Jump regardless
+        // Now setup the labels
+        // True label
+        self.append_cmd(&format!("({}_{})", true_label, self.command_count));
+        self.append_cmd(&format!("D={}", constants::TRUE_VALUE)); // Set the true value in D
+        self.append_cmd(&format!("@{}_{}", set_val_label, self.command_count)); // Load in set label
+        self.append_cmd("0;JMP"); // Jump to that label
+
+        // False label
+        self.append_cmd(&format!("({}_{})", false_label, self.command_count));
+        self.append_cmd(&format!("D={}", constants::FALSE_VALUE)); // Set the false value in D
+        // Let it fall through into the result label
+
+        // Set value label
+        self.append_cmd(&format!("({}_{})", set_val_label, self.command_count));
+        self.append_cmd("@SP");
+        self.append_cmd("A=M"); // Go to SP
+        self.append_cmd("M=D"); // Set *SP to the result val
+        // Now increment sp
+        self.inc_sp();
+
+
+    }
+
+    /// Label of the shared routine for a comparison
+    /// 
+    /// Ex:
+    ///     eq
+    ///         -> "$$eq"
+    fn comparison_routine(operation: Operation) -> String {
+        return format!("$${}", operation.vm_name());
+    }
+
+    /// Jumps to the shared routine for a comparison with the return address in D
+    fn call_comparison(&mut self, operation: Operation) {
+        let return_addr = self.next_comparison_label();
+        self.shared_comparisons.insert(operation);
+
+        self.set_addr(&return_addr);
+        self.append_cmd("D=A");
+        self.jump_to_label(&CodeGen::comparison_routine(operation));
+        self.append_label(&return_addr);
+    }
+
+    /// Writes the shared routine for a comparison, which replaces the top two values of the stack
+    /// with the result and returns to the address it was given in D
+    fn write_comparison_routine(&mut self, operation: Operation) {
+        let routine = CodeGen::comparison_routine(operation);
+        let jump_ins = match operation {
+            Operation::Eq   => "JEQ",
+            Operation::Gt   => "JGT",
+            _               => "JLT"
+        };
+
+        self.append_label(&routine);
+
+        // Keep the return address in R15
+        self.set_addr("R15");
+        self.append_cmd("M=D");
+
+        self.get_sp1();
+        self.get_sp2();
+        self.append_cmd("D=M-D");
+
+        self.branch_addr(&format!("{}.true", routine), Some(jump_ins));
+        self.append_cmd(&format!("D={}", constants::FALSE_VALUE));
+        self.jump_to_label(&format!("{}.set", routine));
+        self.append_label(&format!("{}.true", routine));
+        self.append_cmd(&format!("D={}", constants::TRUE_VALUE));
+
+        // Set *SP to the result and return
+        self.append_label(&format!("{}.set", routine));
+        self.append_cmd("@SP");
+        self.append_cmd("A=M");
+        self.append_cmd("M=D");
+        self.inc_sp();
+
+        self.set_addr("R15");
+        self.append_cmd("A=M");
+        self.append_cmd("0; JMP");
+    }
+
+    /// Generate the hack commands for an arithmetic operation
+    fn gen_arithmetic(&mut self, operation: Operation) {
+        // Check if it is not or neg as both of those only take one argument
+        if operation == Operation::Neg {
+            self.get_sp1();
+            self.append_cmd("M=-M"); // Make M negative and save
+            self.inc_sp();
+        }
+        else if operation == Operation::Not {
+            self.get_sp1();
+            self.append_cmd("M=!M"); // Negate M
+            self.inc_sp();
+        }
+
+        // Match on operation
+        match operation {
+            Operation::Add  => {
+                self.get_sp1();
+                self.get_sp2();
+                self.append_cmd("M=M+D"); // Insert the new value at SP
+                self.inc_sp();
+            },
+            Operation::Sub  => {
+                self.get_sp1();
+                self.get_sp2();
+                self.append_cmd("M=M-D");
+                self.inc_sp();
+            },
+            Operation::Eq | Operation::Gt | Operation::Lt if self.share_code => {
+                self.call_comparison(operation);
+            },
+            Operation::Eq   => {
+                self.get_sp1();
+                self.get_sp2();
+                self.append_cmd("D=M-D"); // If equal this value is 0
+                self.load_bool_jumps("JEQ"); // We want true if eq
+                // self.inc_sp();
+            },
+            Operation::Gt   => {
+                self.get_sp1();
+                self.get_sp2();
+                self.append_cmd("D=M-D"); // We want x - y which is M - D
+                self.load_bool_jumps("JGT"); // We want true if gt
+                // self.inc_sp();
+            },
+            Operation::Lt   => {
+                self.get_sp1();
+                self.get_sp2();
+                self.append_cmd("D=M-D");
+                self.load_bool_jumps("JLT"); // We want true if lt
+                // self.inc_sp();
+            },
+            Operation::And  => {
+                self.get_sp1();
+                self.get_sp2();
+                self.append_cmd("D=M&D");
+                self.push_d();
+                // self.inc_sp();
+            },
+            Operation::Or   => {
+                self.get_sp1();
+                self.get_sp2();
+                self.append_cmd("D=M|D");
+                self.push_d();
+                // self.inc_sp();
+            }
+            _               => {}
+        }
+
+    }
+
+    // Executes a memory command
+    fn exec_memory(&mut self, op: Operation, segment: Segment, segment_i: u32) {
+        // Save memory location as a string
+        let mut memory_addr = Segment::to_string(&segment);
+
+        // Parse local, argument, this, that
+        if segment == Segment::Local || segment == Segment::Argument
+            || segment == Segment::This || segment == Segment::That {
+
+            if op == Operation::Push {
+                // Get the offset in the d register
+                self.set_d(segment_i);
+                // Get to the new memory address and add in the offset
+                self.append_cmd(&format!("@{}", memory_addr));
+                self.append_cmd("A=D+M"); // Go to the address
+
+                // Get value at ram in d reg
+                self.append_cmd("D=M");
+                // Now push d
+                self.push_d();
+            }
+            else if op == Operation::Pop {
+                // Get the offset in d
+                self.set_d(segment_i);
+                
+                // Now go to the base and get address (base + i) in d
+                self.append_cmd(&format!("@{}", memory_addr));
+                self.append_cmd("D=M+D");
+
+                // Now save d in R13
+                self.append_cmd("@R13");
+                self.append_cmd("M=D");
+
+                // Get SP value into d
+                self.pop_d();
+
+                // Go to R13 and follow the pointer
+                self.append_cmd("@R13");
+                self.append_cmd("A=M");
+
+                // Now Save D into M
+                self.append_cmd("M=D");
+
+            }
+            else {
+                panic!("Impossible error");
+            }
+        }
+        else if segment == Segment::Static {
+            if op == Operation::Push {
+                // Go to memory location
+                self.append_cmd(&format!("@{}.{}", self.program_name, segment_i));
+                // Get the value in d
+                self.append_cmd("D=M");
+                // Push d to the stack
+                self.push_d();
+            }
+            else if op == Operation::Pop {
+                // Pop d
+                self.pop_d();
+                // Go to memory location
+                self.append_cmd(&format!("@{}.{}", self.program_name, segment_i));
+                // Set M to d
+                self.append_cmd("M=D");
+            }
+            else {
+                panic!("Impossible error");
+            }
+        }
+        else if segment == Segment::Temp {
+            let addr = constants::TEMP_START + segment_i;
+
+            if op == Operation::Push {
+                // Go to address
+                self.append_cmd(&format!("@{}", addr));
+                // Set d to m
+                self.append_cmd("D=M");
+                // Push d
+                self.push_d();        
+            }
+            else if op == Operation::Pop {
+                // Pop d
+                self.pop_d();
+                // Go to address
+                self.append_cmd(&format!("@{}", addr));
+                // Set m to d
+                self.append_cmd("M=D");
+            }
+            else {
+                panic!("Impossible error");
+            }
+        }
+        else if segment == Segment::Pointer {
+            // Get corresponding segment
+            memory_addr = if segment_i == 0 {"THIS".to_owned()} else {"THAT".to_owned()};
+
+            if op == Operation::Push {
+                // Go to either this or that
+                self.append_cmd(&format!("@{}", memory_addr));
+                // Store address on stack
+                // ISSUE ? This might need to be M instead of D
+                self.append_cmd("D=M");
+                self.push_d();
+            }
+            else if op == Operation::Pop {
+                // Pop D
+                self.pop_d();
+                // Go to this or that and store value into it from stack
+                self.append_cmd(&format!("@{}", memory_addr));
+                self.append_cmd("M=D");
+            }
+            else {
+                panic!("Impossible error");
+            }
+        }
+        else if segment == Segment::Constant {
+            if op == Operation::Push {
+                // Get the constant value
+                self.set_d(segment_i);
+                self.push_d();
+            }
+            else if op == Operation::Pop {
+                panic!("Impossible to pop a constant");
+            }
+            else {
+                panic!("Impossible error");
+            }
+        }
+        else {
+            panic!("No code for segment {:?}", segment);
+        }
+    }
+}
@@ -3,4 +3,40 @@ pub const TRUE_VALUE: i32 = -1;
 /// Number to insert in ram on false
 pub const FALSE_VALUE: i32 = 0;
 /// Keeps track of the beginning of the temp segment
-pub const TEMP_START: u32 = 5;
\ No newline at end of file
+pub const TEMP_START: u32 = 5;
+/// Number of registers in the temp segment
+pub const TEMP_SIZE: u32 = 8;
+/// Number of RAM words (16 - 255) static variables can use
+pub const STATIC_SIZE: u32 = 240;
+/// Largest value that can be loaded with an A-instruction
+pub const MAX_CONSTANT: u16 = 32767;
+/// Number of instructions the Hack ROM holds
+pub const ROM_SIZE: usize = 32768;
+/// First RAM address given to assembly variables
+pub const VARIABLE_START: u16 = 16;
+/// Stack pointer value set by the bootstrap code
+pub const DEFAULT_SP: u16 = 256;
+/// Function called by the bootstrap code
+pub const DEFAULT_ENTRY_POINT: &str = "Sys.init";
+/// Path meaning stdin when reading and stdout when writing
+pub const STDIO_PATH: &str = "-";
+/// Name VM code read from stdin is reported under
+pub const STDIN_NAME: &str = "<stdin>";
+/// Module name of VM code read from stdin when none is given
+pub const DEFAULT_MODULE: &str = "Main";
+/// Module name the bootstrap code is generated under
+pub const BOOTSTRAP_MODULE: &str = "Bootstrap";
+/// Module name the shared routines are generated under
+pub const RUNTIME_MODULE: &str = "Runtime";
+/// Label the program continues at after jumping over the shared routines
+pub const RUNTIME_START: &str = "$$start";
+/// Label of the shared call routine
+pub const CALL_ROUTINE: &str = "$$call";
+/// Label of the shared return routine
+pub const RETURN_ROUTINE: &str = "$$return";
+/// First RAM address of the stack, after the static variables
+pub const STACK_START: u16 = 256;
+/// First RAM address after the stack
+pub const HEAP_START: u16 = 2048;
+/// General purpose registers (R13 - R15) the generated code uses as scratch space
+pub const SCRATCH_REGISTERS: std::ops::RangeInclusive<u16> = 13..=15;
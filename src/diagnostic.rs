@@ -0,0 +1,106 @@
+use core::fmt;
+use std::error::Error;
+
+use crate::error::TranslateError;
+
+/// Location of a piece of VM source code
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Span {
+    /// Path of the file the code was read from
+    pub file: String,
+    /// Line number (1-based)
+    pub line: usize,
+    /// First column covered (1-based)
+    pub start_col: usize,
+    /// Column just past the end of the covered text
+    pub end_col: usize
+}
+
+impl Span {
+    pub fn new(file: &str, line: usize, start_col: usize, end_col: usize) -> Self {
+        return Span {
+            file: file.to_owned(),
+            line,
+            start_col,
+            end_col
+        };
+    }
+
+    /// Returns a span on the same line covering the given columns
+    pub fn with_columns(&self, start_col: usize, end_col: usize) -> Self {
+        return Span {
+            file: self.file.clone(),
+            line: self.line,
+            start_col,
+            end_col
+        };
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.start_col)
+    }
+}
+
+/// How serious a diagnostic is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Translation cannot produce output
+    Error,
+    /// Suspicious code that is still translated
+    Warning
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error     => write!(f, "error"),
+            Severity::Warning   => write!(f, "warning")
+        }
+    }
+}
+
+/// An error or warning along with where in the source it was found
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Location of the offending code, if it came from a source file
+    pub span: Option<Span>,
+    pub error: TranslateError
+}
+
+impl Diagnostic {
+    pub fn error(span: Option<Span>, error: TranslateError) -> Self {
+        return Diagnostic { severity: Severity::Error, span, error };
+    }
+
+    pub fn warning(span: Option<Span>, error: TranslateError) -> Self {
+        return Diagnostic { severity: Severity::Warning, span, error };
+    }
+
+    pub fn is_error(&self) -> bool {
+        return self.severity == Severity::Error;
+    }
+}
+
+impl From<TranslateError> for Diagnostic {
+    fn from(error: TranslateError) -> Self {
+        return Diagnostic::error(None, error);
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.span {
+            Some(span)  => write!(f, "{}: {}: {}", span, self.severity, self.error),
+            None        => write!(f, "{}: {}", self.severity, self.error)
+        }
+    }
+}
+
+impl Error for Diagnostic {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        return Some(&self.error);
+    }
+}
@@ -0,0 +1,299 @@
+use core::fmt;
+use std::collections::BTreeSet;
+use std::ops::Range;
+
+use crate::assembler;
+use crate::constants;
+use crate::diagnostic::{Diagnostic, Span};
+use crate::emulator::{Cpu, StopReason};
+use crate::interpreter::VmInterpreter;
+use crate::translator::{self, Translation};
+
+/// Most instructions a single VM command may take on the emulator before it is considered stuck
+const MAX_COMMAND_CYCLES: u64 = 1_000_000;
+
+/// How the translated program stopped matching the VM interpreter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mismatch {
+    /// A word of RAM holds a different value
+    Memory { addr: u16, expected: i16, actual: i16 },
+    /// The emulator left the command's code for somewhere other than the next command
+    ControlFlow { expected: u16, actual: u16 },
+    /// A static variable the VM program uses isn't in the translated program
+    MissingStatic { name: String }
+}
+
+/// First point where the translated program and the VM interpreter disagree
+#[derive(Debug, Clone)]
+pub struct Divergence {
+    /// Index of the command that was just executed
+    pub command: usize,
+    /// Location of the command in its source file
+    pub span: Option<Span>,
+    /// VM code of the command, empty for generated code like the bootstrap
+    pub vm_code: String,
+    pub mismatch: Mismatch
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(span) = &self.span {
+            write!(f, "{}: ", span)?;
+        }
+
+        if self.vm_code.is_empty() {
+            write!(f, "in generated code: ")?;
+        }
+        else {
+            write!(f, "after '{}': ", self.vm_code)?;
+        }
+
+        match &self.mismatch {
+            Mismatch::Memory { addr, expected, actual } => {
+                write!(f, "RAM[{}] is {} in the translated program but {} in the VM interpreter", addr, actual, expected)
+            },
+            Mismatch::ControlFlow { expected, actual } => {
+                write!(f, "translated program jumped to ROM[{}] instead of ROM[{}]", actual, expected)
+            },
+            Mismatch::MissingStatic { name } => {
+                write!(f, "static {} is missing from the translated program", name)
+            }
+        }
+    }
+}
+
+/// Result of running a program on both the emulator and the VM interpreter
+pub struct Comparison {
+    /// Number of VM commands executed
+    pub steps: u64,
+    /// Number of instructions the emulator executed
+    pub cycles: u64,
+    /// Why the VM interpreter stopped
+    pub stop_reason: StopReason,
+    /// First difference found, None if the two always agreed
+    pub divergence: Option<Divergence>
+}
+
+/// Runs the translated program on the emulator in lockstep with the VM interpreter
+/// 
+/// After every VM command the emulator is run until it reaches the code of the next command the
+/// interpreter will execute, then every RAM word either of them wrote is compared. Commands the
+/// optimizer fused with the next one, and generated code like the bootstrap, are run together with
+/// the next command before comparing. Words that can't match are skipped: the scratch registers,
+/// the stack above SP and return addresses (command indices in the interpreter, ROM addresses in
+/// the emulator)
+/// 
+/// Statics are moved in the interpreter to the addresses the assembler gave them, which only
+/// match the VM emulator's layout when no other variable comes before them
+/// 
+/// ram is set on both before running, for programs without bootstrap code
+/// 
+/// Fails if the program calls an undeclared function, if the translation can't be assembled or if
+/// the VM program hits a runtime error
+pub fn compare(translation: &Translation, ram: &[(u16, i16)], max_steps: u64) -> Result<Comparison, Vec<Diagnostic>> {
+    let undefined_calls = translator::check_calls(translation.commands(), true);
+    if !undefined_calls.is_empty() {
+        return Err(undefined_calls);
+    }
+
+    let machine_code = assembler::assemble("", &translation.asm())?;
+    let rom_ranges = translation.rom_ranges();
+    let rom_len = machine_code.instructions.len() as u16;
+
+    let mut cpu = Cpu::new(machine_code.instructions);
+    let mut interpreter = VmInterpreter::new(translation);
+
+    for (addr, value) in ram.iter() {
+        cpu.set_ram(*addr, *value);
+        interpreter.set_ram(*addr, *value);
+    }
+
+    let divergence_at = |command: usize, mismatch: Mismatch| {
+        let vm_command = translation.commands().get(command);
+        return Divergence {
+            command,
+            span: vm_command.and_then(|c| c.span().cloned()),
+            vm_code: vm_command.and_then(|c| c.instruction()).map_or(String::new(), |i| i.to_string()),
+            mismatch
+        };
+    };
+
+    let comparison = |interpreter: &VmInterpreter, cpu: &Cpu, divergence| {
+        return Comparison {
+            steps: interpreter.steps(),
+            cycles: cpu.cycles(),
+            stop_reason: interpreter.stop_reason().unwrap_or(StopReason::CycleLimit),
+            divergence
+        };
+    };
+
+    // Statics have to be at the same addresses for the rest of the comparison to make sense
+    let mut statics: Vec<(String, u16)> = interpreter.statics().iter().map(|(name, addr)| (name.clone(), *addr)).collect();
+    statics.sort_by_key(|(_, addr)| *addr);
+
+    for (name, _) in statics {
+        match machine_code.symbols.get(&name) {
+            Some(addr)  => interpreter.place_static(&name, *addr),
+            None        => {
+                let mismatch = Mismatch::MissingStatic { name };
+                return Ok(comparison(&interpreter, &cpu, Some(divergence_at(0, mismatch))));
+            }
+        }
+    }
+
+    // Generated code like the shared routines can be jumped to from any command
+    let shared_ranges: Vec<Range<u16>> = translation.commands().iter().zip(rom_ranges.iter())
+        .filter(|(command, _)| command.instruction().is_none())
+        .map(|(_, range)| range.clone())
+        .collect();
+
+    // Start of the code of the first fused command the emulator hasn't run yet
+    let mut fused_start = None;
+
+    for _ in 0..max_steps {
+        if interpreter.stop_reason().is_some() {
+            break;
+        }
+
+        let current = interpreter.pc();
+        interpreter.step().map_err(|d| vec![d])?;
+
+        // Fused commands and generated code (which the interpreter skips) only ever fall through
+        // into the next command
+        let is_generated = translation.commands()[current].instruction().is_none();
+        if translation.is_fused(current) || is_generated {
+            fused_start.get_or_insert(rom_ranges[current].start);
+            continue;
+        }
+
+        let start = fused_start.take().unwrap_or(rom_ranges[current].start);
+        let current_range = start..rom_ranges[current].end;
+        let target = rom_ranges.get(interpreter.pc()).map_or(rom_len, |range| range.start);
+
+        if let Some(mismatch) = run_command(&mut cpu, current_range, &shared_ranges, target) {
+            return Ok(comparison(&interpreter, &cpu, Some(divergence_at(current, mismatch))));
+        }
+
+        if let Some(mismatch) = compare_written(&mut interpreter, &mut cpu) {
+            return Ok(comparison(&interpreter, &cpu, Some(divergence_at(current, mismatch))));
+        }
+    }
+
+    return Ok(comparison(&interpreter, &cpu, None));
+}
+
+/// Runs the emulator through the code of one command until it gets to target
+/// 
+/// Leaving the command's code for anywhere but shared code is a mismatch
+fn run_command(cpu: &mut Cpu, range: Range<u16>, shared_ranges: &[Range<u16>], target: u16) -> Option<Mismatch> {
+    // Commands without any code (labels, functions without locals) don't move the emulator
+    if range.is_empty() {
+        if cpu.pc() == target {
+            return None;
+        }
+        return Some(Mismatch::ControlFlow { expected: target, actual: cpu.pc() });
+    }
+
+    for executed in 0..MAX_COMMAND_CYCLES {
+        if cpu.pc() == target && executed > 0 {
+            return None;
+        }
+        let is_shared = shared_ranges.iter().any(|shared| shared.contains(&cpu.pc()));
+        if !range.contains(&cpu.pc()) && !is_shared {
+            return Some(Mismatch::ControlFlow { expected: target, actual: cpu.pc() });
+        }
+
+        cpu.step();
+    }
+
+    return Some(Mismatch::ControlFlow { expected: target, actual: cpu.pc() });
+}
+
+/// Compares every word either side wrote since the last comparison
+fn compare_written(interpreter: &mut VmInterpreter, cpu: &mut Cpu) -> Option<Mismatch> {
+    let mut written = BTreeSet::new();
+    written.extend(interpreter.take_written());
+    written.extend(cpu.take_written());
+
+    // Registers are always compared so a bad SP is reported before what it causes
+    written.extend(0..constants::TEMP_START as u16);
+
+    let sp = interpreter.ram()[0] as u16;
+
+    for addr in written {
+        let is_scratch = constants::SCRATCH_REGISTERS.contains(&addr);
+        let is_free_stack = addr >= sp && (constants::STACK_START..constants::HEAP_START).contains(&addr);
+
+        if is_scratch || is_free_stack || interpreter.return_cells().contains(&addr) {
+            continue;
+        }
+
+        let expected = interpreter.ram()[addr as usize];
+        let actual = cpu.ram()[addr as usize];
+
+        if expected != actual {
+            return Some(Mismatch::Memory { addr, expected, actual });
+        }
+    }
+
+    return None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::TranslateError;
+    use crate::translator::Translator;
+
+    /// Translates the files of a program with its bootstrap code
+    fn translate(files: &[(&str, &str)]) -> Translation {
+        let mut translator = Translator::new();
+        translator.bootstrap(true);
+
+        for (name, text) in files {
+            translator.add_source(name, text);
+        }
+
+        return translator.translate().unwrap();
+    }
+
+    #[test]
+    fn fibonacci_matches_interpreter() {
+        let translation = translate(&[
+            ("Main.vm", include_str!("../tests/fixtures/FibonacciElement/Main.vm")),
+            ("Sys.vm", include_str!("../tests/fixtures/FibonacciElement/Sys.vm"))
+        ]);
+
+        let comparison = compare(&translation, &[], 1000).unwrap();
+
+        assert!(comparison.divergence.is_none(), "{}", comparison.divergence.unwrap());
+        assert_eq!(comparison.stop_reason, StopReason::Halted);
+    }
+
+    #[test]
+    fn statics_match_interpreter() {
+        let translation = translate(&[
+            ("Class1.vm", include_str!("../tests/fixtures/StaticsTest/Class1.vm")),
+            ("Class2.vm", include_str!("../tests/fixtures/StaticsTest/Class2.vm")),
+            ("Sys.vm", include_str!("../tests/fixtures/StaticsTest/Sys.vm"))
+        ]);
+
+        let comparison = compare(&translation, &[], 1000).unwrap();
+
+        assert!(comparison.divergence.is_none(), "{}", comparison.divergence.unwrap());
+        assert_eq!(comparison.stop_reason, StopReason::Halted);
+    }
+
+    #[test]
+    fn undefined_call_fails_before_running() {
+        // The assembler makes Missing.f a variable at RAM[16], moving the statics after it
+        let translation = translate(&[
+            ("Sys.vm", "function Sys.init 0\ncall Missing.f 0\npush constant 1\npop static 0\nlabel END\ngoto END\n")
+        ]);
+
+        let diagnostics = compare(&translation, &[], 1000).err().unwrap();
+
+        assert_eq!(diagnostics.len(), 1);
+        assert!(matches!(&diagnostics[0].error, TranslateError::UndefinedFunction(name) if name == "Missing.f"));
+    }
+}
@@ -0,0 +1,369 @@
+use core::fmt;
+use std::fs::read_to_string;
+use std::path::Path;
+
+use crate::assembler;
+use crate::constants;
+use crate::diagnostic::{Diagnostic, Span};
+use crate::error::TranslateError;
+use crate::parser::Parser;
+use crate::translator::TranslateOptions;
+
+/// Number of words in the Hack RAM (every address an A-instruction can load)
+pub const RAM_SIZE: usize = 32768;
+
+/// RAM addresses written since they were last taken
+/// 
+/// Holds one bit per word, so it stays the same size however long a program runs without the
+/// addresses being taken
+#[derive(Clone)]
+pub struct WrittenSet {
+    bits: Vec<u64>
+}
+
+impl WrittenSet {
+    pub fn new() -> Self {
+        return WrittenSet { bits: vec![0; RAM_SIZE / 64] };
+    }
+
+    pub fn insert(&mut self, addr: u16) {
+        let addr = (addr & 0x7fff) as usize;
+        self.bits[addr / 64] |= 1 << (addr % 64);
+    }
+
+    pub fn clear(&mut self) {
+        self.bits.fill(0);
+    }
+
+    /// Takes every address in the set in ascending order, leaving it empty
+    pub fn take(&mut self) -> Vec<u16> {
+        let mut addrs = Vec::new();
+
+        for (word_i, word) in self.bits.iter_mut().enumerate() {
+            while *word != 0 {
+                let bit = word.trailing_zeros() as usize;
+                addrs.push((word_i * 64 + bit) as u16);
+                *word &= *word - 1;
+            }
+        }
+
+        return addrs;
+    }
+}
+
+impl Default for WrittenSet {
+    fn default() -> Self {
+        return WrittenSet::new();
+    }
+}
+
+/// Why the emulator stopped running
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// Program counter ran past the last instruction
+    EndOfProgram,
+    /// Program jumped back into the same state without writing memory, so it will loop forever
+    Halted,
+    /// Ran for the maximum number of cycles
+    CycleLimit
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::EndOfProgram    => write!(f, "reached the end of the program"),
+            StopReason::Halted          => write!(f, "halted in an infinite loop"),
+            StopReason::CycleLimit      => write!(f, "reached the cycle limit")
+        }
+    }
+}
+
+/// Emulator of the Hack CPU with its instruction ROM and data RAM
+pub struct Cpu {
+    rom: Vec<u16>,
+    ram: Vec<i16>,
+    a: i16,
+    d: i16,
+    pc: u16,
+    /// Number of instructions executed
+    cycles: u64,
+    /// Number of writes to RAM, used to tell a halt loop from a busy loop
+    writes: u64,
+    /// Addresses written since they were last taken
+    written: WrittenSet,
+    /// State the last time a jump was taken (jump address, target, A, D, writes)
+    last_jump: Option<(u16, u16, i16, i16, u64)>,
+    halted: bool,
+    /// Program counter ran past the last address it can hold
+    ended: bool
+}
+
+impl Cpu {
+    pub fn new(rom: Vec<u16>) -> Self {
+        return Cpu {
+            rom,
+            ram: vec![0; RAM_SIZE],
+            a: 0,
+            d: 0,
+            pc: 0,
+            cycles: 0,
+            writes: 0,
+            written: WrittenSet::new(),
+            last_jump: None,
+            halted: false,
+            ended: false
+        };
+    }
+
+    /// Puts the CPU back at the start of the program with cleared registers and RAM
+    pub fn reset(&mut self) {
+        self.ram.fill(0);
+        self.a = 0;
+        self.d = 0;
+        self.pc = 0;
+        self.cycles = 0;
+        self.writes = 0;
+        self.written.clear();
+        self.last_jump = None;
+        self.halted = false;
+        self.ended = false;
+    }
+
+    pub fn ram(&self) -> &[i16] {
+        return &self.ram;
+    }
+
+    /// Sets a word of RAM from outside of the program (doesn't count as a program write)
+    pub fn set_ram(&mut self, addr: u16, value: i16) {
+        self.ram[(addr & 0x7fff) as usize] = value;
+    }
+
+    pub fn a(&self) -> i16 {
+        return self.a;
+    }
+
+    pub fn d(&self) -> i16 {
+        return self.d;
+    }
+
+    pub fn pc(&self) -> u16 {
+        return self.pc;
+    }
+
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    pub fn cycles(&self) -> u64 {
+        return self.cycles;
+    }
+
+    /// Takes the addresses the program has written to since the last call, in ascending order
+    pub fn take_written(&mut self) -> Vec<u16> {
+        return self.written.take();
+    }
+
+    /// Checks if the program can't execute any more instructions
+    pub fn stop_reason(&self) -> Option<StopReason> {
+        if self.halted {
+            return Some(StopReason::Halted);
+        }
+        if self.ended || self.pc as usize >= self.rom.len() {
+            return Some(StopReason::EndOfProgram);
+        }
+        return None;
+    }
+
+    /// Runs until the program stops or max_cycles instructions have been executed
+    pub fn run(&mut self, max_cycles: u64) -> StopReason {
+        for _ in 0..max_cycles {
+            if let Some(reason) = self.stop_reason() {
+                return reason;
+            }
+            self.step();
+        }
+
+        return self.stop_reason().unwrap_or(StopReason::CycleLimit);
+    }
+
+    /// Executes a single instruction
+    pub fn step(&mut self) {
+        let instruction = match self.rom.get(self.pc as usize) {
+            Some(instruction) if !self.ended    => *instruction,
+            _                                   => return
+        };
+
+        self.cycles += 1;
+
+        // A-instruction
+        if instruction & 0x8000 == 0 {
+            self.a = instruction as i16;
+            self.next_instruction();
+            return;
+        }
+
+        // C-instruction: 111a cccc ccdd djjj
+        // M and the jump target both use the value of A from before this instruction
+        let m_addr = self.a as u16 & 0x7fff;
+        let target = self.a as u16;
+
+        let y = if instruction & 0x1000 != 0 { self.ram[m_addr as usize] } else { self.a };
+        let out = alu(self.d, y, (instruction >> 6) & 0x3f);
+
+        if instruction & 0x0008 != 0 {
+            self.ram[m_addr as usize] = out;
+            self.writes += 1;
+            self.written.insert(m_addr);
+        }
+        if instruction & 0x0020 != 0 {
+            self.a = out;
+        }
+        if instruction & 0x0010 != 0 {
+            self.d = out;
+        }
+
+        let jump = (instruction & 0x4 != 0 && out < 0)
+            || (instruction & 0x2 != 0 && out == 0)
+            || (instruction & 0x1 != 0 && out > 0);
+
+        if jump {
+            let state = (self.pc, target, self.a, self.d, self.writes);
+
+            // Coming back around to the same jump with nothing changed means we'll loop forever
+            if self.last_jump == Some(state) {
+                self.halted = true;
+            }
+
+            self.last_jump = Some(state);
+            self.pc = target;
+        }
+        else {
+            self.next_instruction();
+        }
+    }
+
+    /// Moves on to the next instruction, stopping at the end of the addresses pc can hold
+    fn next_instruction(&mut self) {
+        match self.pc.checked_add(1) {
+            Some(pc)    => self.pc = pc,
+            None        => self.ended = true
+        }
+    }
+}
+
+/// Hack ALU for the six c bits (zx nx zy ny f no)
+fn alu(x: i16, y: i16, c: u16) -> i16 {
+    let mut x = if c & 0b100000 != 0 { 0 } else { x };
+    if c & 0b010000 != 0 {
+        x = !x;
+    }
+
+    let mut y = if c & 0b001000 != 0 { 0 } else { y };
+    if c & 0b000100 != 0 {
+        y = !y;
+    }
+
+    let out = if c & 0b000010 != 0 { x.wrapping_add(y) } else { x & y };
+
+    if c & 0b000001 != 0 {
+        return !out;
+    }
+    return out;
+}
+
+/// Reads a program into ROM words from a .hack file, an .asm file, or a .vm file/directory which is translated first
+pub fn load_rom(path: &str, options: TranslateOptions) -> Result<Vec<u16>, Vec<Diagnostic>> {
+    let extension = Path::new(path).extension().and_then(|ext| ext.to_str()).unwrap_or("");
+
+    match extension {
+        "hack" => {
+            let text = read_to_string(path).map_err(|e| vec![TranslateError::io(path, e).into()])?;
+            return parse_hack(path, &text);
+        },
+        "asm" => {
+            let text = read_to_string(path).map_err(|e| vec![TranslateError::io(path, e).into()])?;
+            let lines: Vec<&str> = text.lines().collect();
+            return Ok(assembler::assemble(path, &lines)?.instructions);
+        },
+        _ => {
+            let mut options = options;
+            options.executable = true;
+
+            let parser = Parser::new(path, options)?;
+            let asm_name = Path::new(path).with_extension("asm");
+            return Ok(assembler::assemble(&asm_name.to_string_lossy(), &parser.translation().asm())?.instructions);
+        }
+    }
+}
+
+/// Reads machine code in the .hack text format
+pub fn parse_hack(name: &str, text: &str) -> Result<Vec<u16>, Vec<Diagnostic>> {
+    let mut rom = Vec::new();
+    let mut diagnostics = Vec::new();
+    let mut size = 0;
+    // First instruction that doesn't fit in the ROM
+    let mut overflow_span = None;
+
+    for (line_i, line) in text.lines().enumerate() {
+        let code = line.trim();
+
+        if code.is_empty() {
+            continue;
+        }
+
+        if size == constants::ROM_SIZE {
+            overflow_span = Some(Span::new(name, line_i + 1, 1, line.chars().count() + 1));
+        }
+        size += 1;
+
+        match u16::from_str_radix(code, 2) {
+            Ok(instruction) if code.len() == 16 => rom.push(instruction),
+            _ => diagnostics.push(Diagnostic::error(
+                Some(Span::new(name, line_i + 1, 1, line.chars().count() + 1)),
+                TranslateError::InvalidInstruction {
+                    instruction: code.to_owned(),
+                    reason: "not a 16 bit binary instruction".to_owned()
+                }
+            ))
+        }
+    }
+
+    if overflow_span.is_some() {
+        diagnostics.push(Diagnostic::error(overflow_span, TranslateError::RomOverflow {
+            size,
+            max: constants::ROM_SIZE,
+            largest: Vec::new()
+        }));
+    }
+
+    if !diagnostics.is_empty() {
+        diagnostics.sort_by(|a, b| a.span.cmp(&b.span));
+        return Err(diagnostics);
+    }
+
+    return Ok(rom);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stops_at_the_last_address_pc_can_hold() {
+        let mut cpu = Cpu::new(vec![0; u16::MAX as usize + 1]);
+
+        assert_eq!(cpu.run(70_000), StopReason::EndOfProgram);
+        assert_eq!(cpu.cycles(), u16::MAX as u64 + 1);
+    }
+
+    #[test]
+    fn rejects_hack_larger_than_rom() {
+        let text = "0000000000000000\n".repeat(constants::ROM_SIZE + 1);
+
+        let diagnostics = parse_hack("big.hack", &text).err().unwrap();
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].span.as_ref().unwrap().line, constants::ROM_SIZE + 1);
+        assert!(matches!(diagnostics[0].error, TranslateError::RomOverflow { size, .. } if size == constants::ROM_SIZE + 1));
+    }
+}
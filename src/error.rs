@@ -0,0 +1,170 @@
+use core::fmt;
+use std::error::Error;
+use std::io;
+
+/// Errors that can occur while parsing or translating VM code
+#[derive(Debug)]
+pub enum TranslateError {
+    /// Command keyword is not a supported operation
+    UnknownOperation(String),
+    /// Memory segment is not a supported segment
+    UnknownSegment(String),
+    /// Command is missing one of its operands
+    MissingOperand {
+        command: String,
+        operand: &'static str
+    },
+    /// Operand that should be a non-negative number is not
+    InvalidIndex(String),
+    /// Label or function name that can't be used as an assembly symbol
+    InvalidName(String),
+    /// Index is outside of the bounds of a fixed size segment
+    IndexOutOfRange {
+        segment: String,
+        index: u32,
+        max: u32
+    },
+    /// Argument or local count of a call or function that doesn't fit in an A-instruction
+    CountOutOfRange {
+        command: String,
+        count: u32,
+        max: u32
+    },
+    /// Tried to pop into the constant segment
+    PopConstant,
+    /// Arithmetic instruction holding an operation that isn't arithmetic
+    NotArithmetic(String),
+    /// Token after all of the operands a command takes
+    UnexpectedToken(String),
+    /// Goto or if-goto to a label that isn't declared in the same function
+    UndefinedLabel {
+        label: String,
+        scope: String
+    },
+    /// Generated code declares the same label more than once
+    DuplicateLabel(String),
+    /// Bootstrap code calls a function that is never declared
+    MissingEntryPoint(String),
+    /// Translation setting has a value that can't be used
+    InvalidOption(String),
+    /// Call to a function that is never declared
+    UndefinedFunction(String),
+    /// Function declared more than once across the program
+    DuplicateFunction {
+        name: String,
+        /// Where it was first declared (file:line:col)
+        first: Option<String>
+    },
+    /// Return jumped to a value that isn't the address of a command
+    InvalidReturnAddress(i16),
+    /// Assembly instruction that can't be assembled
+    InvalidInstruction {
+        instruction: String,
+        reason: String
+    },
+    /// Program has more instructions than fit in the ROM
+    RomOverflow {
+        size: usize,
+        max: usize,
+        /// Largest functions and their sizes, to show what to shrink
+        largest: Vec<(String, usize)>
+    },
+    /// Test script command that can't be run
+    InvalidScript(String),
+    /// Test script output line doesn't match the compare file
+    ComparisonFailure {
+        expected: String,
+        actual: String
+    },
+    /// Project manifest that can't be read
+    InvalidManifest(String),
+    /// File chosen to be translated in a certain order isn't one of the project's vm files
+    UnknownFile(String),
+    /// Input directory without any vm files to translate
+    NoVmFiles(String),
+    /// Same vm file given more than once, directly or through a directory
+    DuplicateInput(String),
+    /// Failed to read or write a file
+    Io {
+        path: String,
+        source: io::Error
+    }
+}
+
+impl TranslateError {
+    /// Wraps an io error with the path that caused it
+    pub fn io(path: &str, source: io::Error) -> Self {
+        return TranslateError::Io { path: path.to_owned(), source };
+    }
+}
+
+impl fmt::Display for TranslateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TranslateError::UnknownOperation(op)    => write!(f, "unknown operation '{}'", op),
+            TranslateError::UnknownSegment(seg)     => write!(f, "unknown segment '{}'", seg),
+            TranslateError::MissingOperand { command, operand } => {
+                write!(f, "'{}' is missing its {} operand", command, operand)
+            },
+            TranslateError::InvalidIndex(index)     => write!(f, "'{}' is not a valid index", index),
+            TranslateError::InvalidName(name)       => {
+                write!(f, "'{}' is not a valid name (letters, digits, '_', '.', '$' and ':', not starting with a digit)", name)
+            },
+            TranslateError::IndexOutOfRange { segment, index, max } => {
+                write!(f, "index {} is out of range for segment '{}' (max {})", index, segment, max)
+            },
+            TranslateError::CountOutOfRange { command, count, max } => {
+                write!(f, "count {} is out of range for '{}' (max {})", count, command, max)
+            },
+            TranslateError::PopConstant             => write!(f, "cannot pop into the constant segment"),
+            TranslateError::NotArithmetic(op)       => write!(f, "'{}' is not an arithmetic operation", op),
+            TranslateError::UnexpectedToken(token)  => write!(f, "unexpected token '{}'", token),
+            TranslateError::UndefinedLabel { label, scope } => {
+                write!(f, "label '{}' is not defined in '{}'", label, scope)
+            },
+            TranslateError::DuplicateLabel(label)   => {
+                write!(f, "label '{}' is declared more than once in the generated code", label)
+            },
+            TranslateError::MissingEntryPoint(name) => write!(f, "entry point function '{}' is not declared", name),
+            TranslateError::InvalidOption(message)  => write!(f, "{}", message),
+            TranslateError::UndefinedFunction(name) => write!(f, "function '{}' is not declared", name),
+            TranslateError::DuplicateFunction { name, first: Some(first) } => {
+                write!(f, "function '{}' is already declared at {}", name, first)
+            },
+            TranslateError::DuplicateFunction { name, first: None } => {
+                write!(f, "function '{}' is declared more than once", name)
+            },
+            TranslateError::InvalidReturnAddress(addr) => write!(f, "return to invalid address {}", addr),
+            TranslateError::InvalidInstruction { instruction, reason } => {
+                write!(f, "invalid instruction '{}': {}", instruction, reason)
+            },
+            TranslateError::RomOverflow { size, max, largest } => {
+                write!(f, "program is {} instructions but the ROM only holds {}", size, max)?;
+
+                if !largest.is_empty() {
+                    let sizes: Vec<String> = largest.iter().map(|(name, size)| format!("{} ({})", name, size)).collect();
+                    write!(f, " (largest functions: {})", sizes.join(", "))?;
+                }
+                Ok(())
+            },
+            TranslateError::InvalidScript(message)  => write!(f, "{}", message),
+            TranslateError::ComparisonFailure { expected, actual } => {
+                write!(f, "comparison failure: expected '{}' but got '{}'", expected, actual)
+            },
+            TranslateError::InvalidManifest(message) => write!(f, "invalid project manifest: {}", message),
+            TranslateError::UnknownFile(file)       => write!(f, "'{}' is not one of the project's vm files", file),
+            TranslateError::NoVmFiles(dir)          => write!(f, "no vm files found in '{}'", dir),
+            TranslateError::DuplicateInput(file)    => write!(f, "'{}' is given more than once, only the first is used", file),
+            TranslateError::Io { path, source }     => write!(f, "{}: {}", path, source)
+        }
+    }
+}
+
+impl Error for TranslateError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TranslateError::Io { source, .. } => Some(source),
+            _ => None
+        }
+    }
+}
@@ -0,0 +1,138 @@
+/// Indentation of the commands inside a function
+const INDENT: &str = "    ";
+
+/// Formats VM code with one space between tokens and the commands of each function indented
+///
+/// Comments are kept, runs of blank lines are collapsed into one and the code ends with a
+/// single newline
+///
+/// Ex:
+/// function Main.main 0
+///     push constant 1 // one
+///     return
+pub fn format_vm(text: &str) -> String {
+    let lines: Vec<(String, Option<&str>)> = text.lines().map(split_line).collect();
+
+    let mut formatted = String::new();
+    let mut in_function = false;
+    let mut blank = false;
+
+    for (i, (code, comment)) in lines.iter().enumerate() {
+        if code.is_empty() && comment.is_none() {
+            blank = !formatted.is_empty();
+            continue;
+        }
+
+        if blank {
+            formatted.push('\n');
+            blank = false;
+        }
+
+        // Comments on their own line go with the code that follows them
+        let next_code = if code.is_empty() {
+            lines[i + 1..].iter().map(|(code, _)| code).find(|code| !code.is_empty())
+        }
+        else {
+            Some(code)
+        };
+
+        let declares_function = next_code.is_some_and(|code| is_function(code));
+        if !code.is_empty() && declares_function {
+            in_function = true;
+        }
+
+        if in_function && !declares_function {
+            formatted.push_str(INDENT);
+        }
+
+        formatted.push_str(code);
+
+        if let Some(comment) = comment {
+            if !code.is_empty() {
+                formatted.push(' ');
+            }
+            formatted.push_str("//");
+            formatted.push_str(comment);
+        }
+
+        formatted.push('\n');
+    }
+
+    return formatted;
+}
+
+/// Splits a line into its tokens joined by single spaces and the text of its comment
+fn split_line(line: &str) -> (String, Option<&str>) {
+    let (code, comment) = match line.find("//") {
+        Some(comment_index) => (&line[0..comment_index], Some(line[comment_index + 2..].trim_end())),
+        None                => (line, None)
+    };
+
+    return (code.split_whitespace().collect::<Vec<&str>>().join(" "), comment);
+}
+
+/// Checks if the code is a function declaration
+fn is_function(code: &str) -> bool {
+    return code.split(' ').next() == Some("function");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSY: &str = "\n\n// Computes 1 + 2\nfunction   Main.main 0\n\tpush constant 1   //one\npush constant 2\n\n\n\nadd\n  // done\nreturn\r\n// Next function\n\nfunction Main.f 1\npush local 0\nreturn";
+
+    const FORMATTED: &str = "\
+// Computes 1 + 2
+function Main.main 0
+    push constant 1 //one
+    push constant 2
+
+    add
+    // done
+    return
+// Next function
+
+function Main.f 1
+    push local 0
+    return
+";
+
+    #[test]
+    fn formats_code() {
+        assert_eq!(format_vm(MESSY), FORMATTED);
+    }
+
+    #[test]
+    fn is_idempotent() {
+        assert_eq!(format_vm(FORMATTED), FORMATTED);
+        assert_eq!(format_vm(&format_vm(MESSY)), format_vm(MESSY));
+    }
+
+    #[test]
+    fn leaves_code_outside_functions_unindented() {
+        assert_eq!(format_vm("push constant 1\n  label LOOP\ngoto LOOP"), "push constant 1\nlabel LOOP\ngoto LOOP\n");
+    }
+
+    #[test]
+    fn keeps_comments_with_the_code_after_them() {
+        let text = "function A.a 0\nreturn\n// About B.b\n// More about B.b\nfunction B.b 0\n// Inside B.b\nreturn\n// Trailing";
+
+        assert_eq!(format_vm(text), "\
+function A.a 0
+    return
+// About B.b
+// More about B.b
+function B.b 0
+    // Inside B.b
+    return
+    // Trailing
+");
+    }
+
+    #[test]
+    fn formats_empty_text() {
+        assert_eq!(format_vm(""), "");
+        assert_eq!(format_vm("\n\n  \n"), "");
+    }
+}
@@ -0,0 +1,127 @@
+use core::fmt;
+
+use crate::assembler;
+use crate::constants;
+use crate::error::TranslateError;
+use crate::operations::{Operation, Segment, ARITHMETIC_OPERATION};
+
+/// A single VM command with its operands
+/// 
+/// Produced by parsing a line of VM code and consumed by the code generator
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VmInstruction {
+    /// One of the arithmetic/logical operations (add, sub, neg, eq, gt, lt, and, or, not)
+    Arithmetic(Operation),
+    /// Pushes segment[index] onto the stack
+    Push {
+        segment: Segment,
+        index: u32
+    },
+    /// Pops the top of the stack into segment[index]
+    Pop {
+        segment: Segment,
+        index: u32
+    },
+    /// Marks the current position with a label
+    Label(String),
+    /// Jumps to a label
+    Goto(String),
+    /// Pops the stack and jumps to a label if the value is not false
+    IfGoto(String),
+    /// Declares a function with nlocals local variables
+    Function {
+        name: String,
+        nlocals: u32
+    },
+    /// Calls a function after nargs arguments have been pushed
+    Call {
+        name: String,
+        nargs: u32
+    },
+    /// Returns from the current function
+    Return
+}
+
+impl VmInstruction {
+    /// Operation performed by the instruction
+    pub fn operation(&self) -> Operation {
+        match self {
+            VmInstruction::Arithmetic(op)       => *op,
+            VmInstruction::Push { .. }          => Operation::Push,
+            VmInstruction::Pop { .. }           => Operation::Pop,
+            VmInstruction::Label(_)             => Operation::Label,
+            VmInstruction::Goto(_)              => Operation::Goto,
+            VmInstruction::IfGoto(_)            => Operation::IfGoto,
+            VmInstruction::Function { .. }      => Operation::Function,
+            VmInstruction::Call { .. }          => Operation::Call,
+            VmInstruction::Return               => Operation::Return
+        }
+    }
+
+    /// Checks that the instruction can be translated
+    /// 
+    /// The parser only produces valid instructions, but ones built by hand can hold any
+    /// operation, segment, index or name
+    pub fn validate(&self) -> Result<(), TranslateError> {
+        match self {
+            // Labels and functions become assembly symbols
+            VmInstruction::Label(name) | VmInstruction::Goto(name) | VmInstruction::IfGoto(name)
+            | VmInstruction::Function { name, .. } | VmInstruction::Call { name, .. }
+                if !assembler::is_symbol(name) => Err(TranslateError::InvalidName(name.clone())),
+            // Counts are loaded with an A-instruction, so they must fit in one
+            VmInstruction::Function { nlocals: count, .. } | VmInstruction::Call { nargs: count, .. }
+                if *count > constants::MAX_CONSTANT as u32 => Err(TranslateError::CountOutOfRange {
+                    command: self.operation().vm_name().to_owned(),
+                    count: *count,
+                    max: constants::MAX_CONSTANT as u32
+                }),
+            VmInstruction::Arithmetic(op) if !ARITHMETIC_OPERATION.contains(*op) => {
+                Err(TranslateError::NotArithmetic(format!("{:?}", op)))
+            },
+            VmInstruction::Push { segment, index } => check_segment(Operation::Push, *segment, *index),
+            VmInstruction::Pop { segment, index } => check_segment(Operation::Pop, *segment, *index),
+            _ => Ok(())
+        }
+    }
+}
+
+/// Checks that a push or pop's segment and index can be translated
+fn check_segment(op: Operation, segment: Segment, index: u32) -> Result<(), TranslateError> {
+    let out_of_range = |max: u32| TranslateError::IndexOutOfRange {
+        segment: segment.vm_name().to_owned(),
+        index,
+        max
+    };
+
+    match segment {
+        Segment::Constant if op == Operation::Pop => Err(TranslateError::PopConstant),
+        // Constants and offsets are loaded with an A-instruction, so they must fit in one
+        Segment::Constant | Segment::Local | Segment::Argument | Segment::This | Segment::That
+            if index > constants::MAX_CONSTANT as u32 => Err(out_of_range(constants::MAX_CONSTANT as u32)),
+        Segment::Static if index >= constants::STATIC_SIZE => Err(out_of_range(constants::STATIC_SIZE - 1)),
+        Segment::Temp if index >= constants::TEMP_SIZE => Err(out_of_range(constants::TEMP_SIZE - 1)),
+        Segment::Pointer if index > 1 => Err(out_of_range(1)),
+        Segment::None | Segment::Sp => Err(TranslateError::UnknownSegment(segment.vm_name().to_owned())),
+        _ => Ok(())
+    }
+}
+
+impl fmt::Display for VmInstruction {
+    /// Writes the instruction back out as VM code
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = self.operation().vm_name();
+
+        match self {
+            VmInstruction::Push { segment, index } | VmInstruction::Pop { segment, index } => {
+                write!(f, "{} {} {}", op, segment.vm_name(), index)
+            },
+            VmInstruction::Label(label) | VmInstruction::Goto(label) | VmInstruction::IfGoto(label) => {
+                write!(f, "{} {}", op, label)
+            },
+            VmInstruction::Function { name, nlocals: count } | VmInstruction::Call { name, nargs: count } => {
+                write!(f, "{} {} {}", op, name, count)
+            },
+            _ => write!(f, "{}", op)
+        }
+    }
+}
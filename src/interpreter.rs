@@ -0,0 +1,376 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::constants;
+use crate::diagnostic::{Diagnostic, Span};
+use crate::emulator::{StopReason, WrittenSet, RAM_SIZE};
+use crate::error::TranslateError;
+use crate::instruction::VmInstruction;
+use crate::operations::{Operation, Segment};
+use crate::translator::{label_scopes, Translation};
+
+/// Reference interpreter that executes VM instructions directly
+/// 
+/// Uses the same RAM layout as the translated code (SP, LCL, ARG, THIS, THAT, temp, stack
+/// and heap) and gives static variables the addresses the assembler would, so its state can be
+/// compared word for word with the emulator
+pub struct VmInterpreter {
+    /// Instruction of each translated command, None for generated code like the bootstrap
+    program: Vec<Option<VmInstruction>>,
+    /// Location of each command in its source file
+    spans: Vec<Option<Span>>,
+    /// Function (or module) each command's labels are scoped to
+    scopes: Vec<String>,
+    /// Index of each label by its mangled name (Function$label)
+    labels: HashMap<String, usize>,
+    /// Index of each function declaration
+    functions: HashMap<String, usize>,
+    /// Address of each static variable by its assembly name (Module.i)
+    statics: HashMap<String, u16>,
+    /// Assembly name of the static variable each command uses
+    static_names: Vec<Option<String>>,
+    ram: Vec<i16>,
+    /// Index of the next command to execute
+    pc: usize,
+    /// Number of commands executed
+    steps: u64,
+    /// Number of writes to RAM, used to tell a halt loop from a busy loop
+    writes: u64,
+    /// Addresses written since they were last taken
+    written: WrittenSet,
+    /// Stack addresses currently holding a return address
+    return_cells: HashSet<u16>,
+    /// State the last time a jump was taken (jump index, target, writes)
+    last_jump: Option<(usize, usize, u64)>,
+    halted: bool
+}
+
+impl VmInterpreter {
+    /// Loads the translated program, starting with SP set if it has bootstrap code
+    pub fn new(translation: &Translation) -> Self {
+        let commands = translation.commands();
+        let scopes = label_scopes(commands);
+
+        let mut labels = HashMap::new();
+        let mut functions = HashMap::new();
+        let mut statics = HashMap::new();
+        let mut static_names = Vec::new();
+
+        for (i, (command, scope)) in commands.iter().zip(scopes.iter()).enumerate() {
+            let mut static_name = None;
+
+            match command.instruction() {
+                Some(VmInstruction::Label(label)) => {
+                    labels.insert(format!("{}${}", scope, label), i);
+                },
+                Some(VmInstruction::Function { name, .. }) => {
+                    functions.insert(name.clone(), i);
+                },
+                Some(VmInstruction::Push { segment: Segment::Static, index })
+                | Some(VmInstruction::Pop { segment: Segment::Static, index }) => {
+                    // Statics get addresses from 16 in order of first use, like on the VM emulator.
+                    // The assembler may lay them out differently, see place_static
+                    let name = format!("{}.{}", command.program_name(), index);
+                    let next_addr = constants::VARIABLE_START + statics.len() as u16;
+                    statics.entry(name.clone()).or_insert(next_addr);
+                    static_name = Some(name);
+                },
+                _ => {}
+            }
+
+            static_names.push(static_name);
+        }
+
+        let mut interpreter = VmInterpreter {
+            program: commands.iter().map(|command| command.instruction().cloned()).collect(),
+            spans: commands.iter().map(|command| command.span().cloned()).collect(),
+            scopes,
+            labels,
+            functions,
+            statics,
+            static_names,
+            ram: vec![0; RAM_SIZE],
+            pc: 0,
+            steps: 0,
+            writes: 0,
+            written: WrittenSet::new(),
+            return_cells: HashSet::new(),
+            last_jump: None,
+            halted: false
+        };
+
+        if let Some(sp) = translation.initial_sp() {
+            interpreter.ram[0] = sp as i16;
+        }
+
+        return interpreter;
+    }
+
+    pub fn ram(&self) -> &[i16] {
+        return &self.ram;
+    }
+
+    /// Sets a word of RAM from outside of the program (doesn't count as a program write)
+    pub fn set_ram(&mut self, addr: u16, value: i16) {
+        self.ram[(addr & 0x7fff) as usize] = value;
+    }
+
+    /// Address of each static variable by its assembly name (Module.i)
+    pub fn statics(&self) -> &HashMap<String, u16> {
+        return &self.statics;
+    }
+
+    /// Moves a static variable to another address, so RAM can be compared with translated code
+    /// the assembler laid out differently
+    pub fn place_static(&mut self, name: &str, addr: u16) {
+        if let Some(static_addr) = self.statics.get_mut(name) {
+            *static_addr = addr;
+        }
+    }
+
+    /// Stack addresses currently holding a return address
+    /// 
+    /// These hold command indices here but ROM addresses in translated code
+    pub fn return_cells(&self) -> &HashSet<u16> {
+        return &self.return_cells;
+    }
+
+    /// Index of the next command to execute
+    pub fn pc(&self) -> usize {
+        return self.pc;
+    }
+
+    /// Sets the index of the next command to execute
+    pub fn set_pc(&mut self, pc: usize) {
+        self.pc = pc;
+    }
+
+    /// Index of the declaration of a function, None if it isn't declared
+    pub fn function_index(&self, name: &str) -> Option<usize> {
+        return self.functions.get(name).copied();
+    }
+
+    pub fn steps(&self) -> u64 {
+        return self.steps;
+    }
+
+    /// Takes the addresses the program has written to since the last call, in ascending order
+    pub fn take_written(&mut self) -> Vec<u16> {
+        return self.written.take();
+    }
+
+    /// Checks if the program can't execute any more commands
+    pub fn stop_reason(&self) -> Option<StopReason> {
+        if self.halted {
+            return Some(StopReason::Halted);
+        }
+        if self.pc >= self.program.len() {
+            return Some(StopReason::EndOfProgram);
+        }
+        return None;
+    }
+
+    /// Runs until the program stops or max_steps commands have been executed
+    pub fn run(&mut self, max_steps: u64) -> Result<StopReason, Diagnostic> {
+        for _ in 0..max_steps {
+            if let Some(reason) = self.stop_reason() {
+                return Ok(reason);
+            }
+            self.step()?;
+        }
+
+        return Ok(self.stop_reason().unwrap_or(StopReason::CycleLimit));
+    }
+
+    /// Executes a single command
+    pub fn step(&mut self) -> Result<(), Diagnostic> {
+        let instruction = match self.program.get(self.pc) {
+            Some(instruction)   => instruction.clone(),
+            None                => return Ok(())
+        };
+
+        self.steps += 1;
+        let current = self.pc;
+        self.pc += 1;
+
+        match instruction {
+            Some(VmInstruction::Arithmetic(op)) => self.exec_arithmetic(op),
+            Some(VmInstruction::Push { segment, index }) => {
+                let value = self.read_segment(current, segment, index);
+                self.push(value);
+            },
+            Some(VmInstruction::Pop { segment, index }) => {
+                let value = self.pop();
+                let addr = self.segment_addr(current, segment, index);
+                self.write(addr, value);
+            },
+            Some(VmInstruction::Label(_)) => {},
+            Some(VmInstruction::Goto(label)) => self.jump(current, &label),
+            Some(VmInstruction::IfGoto(label)) => {
+                let condition = self.pop();
+                if condition != constants::FALSE_VALUE as i16 {
+                    self.jump(current, &label);
+                }
+            },
+            Some(VmInstruction::Function { nlocals, .. }) => {
+                for _i in 0..nlocals {
+                    self.push(0);
+                }
+            },
+            Some(VmInstruction::Call { name, nargs }) => self.call(current, &name, nargs)?,
+            Some(VmInstruction::Return) => self.return_func(current)?,
+            None => {}
+        }
+
+        return Ok(());
+    }
+
+    fn write(&mut self, addr: u16, value: i16) {
+        let addr = addr & 0x7fff;
+        self.ram[addr as usize] = value;
+        self.writes += 1;
+        self.written.insert(addr);
+        self.return_cells.remove(&addr);
+    }
+
+    fn read(&self, addr: u16) -> i16 {
+        return self.ram[(addr & 0x7fff) as usize];
+    }
+
+    fn push(&mut self, value: i16) {
+        let sp = self.read(0);
+        self.write(sp as u16, value);
+        self.write(0, sp.wrapping_add(1));
+    }
+
+    fn pop(&mut self) -> i16 {
+        let sp = self.read(0).wrapping_sub(1);
+        self.write(0, sp);
+        return self.read(sp as u16);
+    }
+
+    /// RAM address of segment[index] (constants have none and aren't passed here)
+    fn segment_addr(&self, current: usize, segment: Segment, index: u32) -> u16 {
+        let index = index as u16;
+
+        match segment {
+            Segment::Local      => (self.read(1) as u16).wrapping_add(index),
+            Segment::Argument   => (self.read(2) as u16).wrapping_add(index),
+            Segment::This       => (self.read(3) as u16).wrapping_add(index),
+            Segment::That       => (self.read(4) as u16).wrapping_add(index),
+            Segment::Pointer    => 3 + index,
+            Segment::Temp       => constants::TEMP_START as u16 + index,
+            Segment::Static     => {
+                let name = self.static_names[current].as_ref().unwrap();
+                self.statics[name]
+            },
+            _                   => panic!("Segment {:?} has no address", segment)
+        }
+    }
+
+    fn read_segment(&self, current: usize, segment: Segment, index: u32) -> i16 {
+        if segment == Segment::Constant {
+            return index as i16;
+        }
+        return self.read(self.segment_addr(current, segment, index));
+    }
+
+    fn exec_arithmetic(&mut self, op: Operation) {
+        let to_bool = |b: bool| if b { constants::TRUE_VALUE as i16 } else { constants::FALSE_VALUE as i16 };
+
+        if op == Operation::Neg || op == Operation::Not {
+            let y = self.pop();
+            self.push(if op == Operation::Neg { y.wrapping_neg() } else { !y });
+            return;
+        }
+
+        let y = self.pop();
+        let x = self.pop();
+
+        let result = match op {
+            Operation::Add  => x.wrapping_add(y),
+            Operation::Sub  => x.wrapping_sub(y),
+            Operation::Eq   => to_bool(x == y),
+            Operation::Gt   => to_bool(x > y),
+            Operation::Lt   => to_bool(x < y),
+            Operation::And  => x & y,
+            Operation::Or   => x | y,
+            _               => panic!("{:?} is not an arithmetic operation", op)
+        };
+
+        self.push(result);
+    }
+
+    /// Jumps to a label in the current function
+    fn jump(&mut self, current: usize, label: &str) {
+        let target = self.labels[&format!("{}${}", self.scopes[current], label)];
+
+        // Coming back around to the same jump with nothing written means we'll loop forever
+        let state = (current, target, self.writes);
+        if self.last_jump == Some(state) {
+            self.halted = true;
+        }
+        self.last_jump = Some(state);
+
+        self.pc = target;
+    }
+
+    fn call(&mut self, current: usize, name: &str, nargs: u32) -> Result<(), Diagnostic> {
+        let target = match self.functions.get(name) {
+            Some(target)    => *target,
+            None            => return Err(Diagnostic::error(
+                self.spans[current].clone(),
+                TranslateError::UndefinedFunction(name.to_owned())
+            ))
+        };
+
+        // Push return address
+        let return_cell = self.read(0) as u16;
+        self.push((current + 1) as i16);
+        self.return_cells.insert(return_cell);
+
+        // Push LCL, ARG, THIS, THAT
+        for addr in 1..=4 {
+            let value = self.read(addr);
+            self.push(value);
+        }
+
+        // ARG = SP - 5 - nArgs
+        let sp = self.read(0);
+        self.write(2, sp.wrapping_sub(5).wrapping_sub(nargs as i16));
+
+        // LCL = SP
+        self.write(1, sp);
+
+        self.pc = target;
+        return Ok(());
+    }
+
+    fn return_func(&mut self, current: usize) -> Result<(), Diagnostic> {
+        let end_frame = self.read(1) as u16;
+        let return_addr = self.read(end_frame.wrapping_sub(5));
+
+        // *ARG = pop()
+        let value = self.pop();
+        let arg = self.read(2);
+        self.write(arg as u16, value);
+
+        // SP = ARG + 1
+        self.write(0, arg.wrapping_add(1));
+
+        // Restore THAT, THIS, ARG, LCL
+        for addr in (1..=4).rev() {
+            let value = self.read(end_frame.wrapping_sub(5 - addr));
+            self.write(addr, value);
+        }
+
+        if return_addr < 0 || return_addr as usize > self.program.len() {
+            return Err(Diagnostic::error(
+                self.spans[current].clone(),
+                TranslateError::InvalidReturnAddress(return_addr)
+            ));
+        }
+
+        self.pc = return_addr as usize;
+        return Ok(());
+    }
+}
@@ -0,0 +1,24 @@
+#![allow(clippy::needless_return)]
+
+pub mod assembler;
+pub mod codegen;
+pub mod constants;
+pub mod diagnostic;
+pub mod differential;
+pub mod emulator;
+pub mod error;
+pub mod formatter;
+pub mod instruction;
+pub mod interpreter;
+pub mod manifest;
+mod optimizer;
+pub mod operations;
+pub mod parser;
+pub mod source_map;
+pub mod test_script;
+mod translator;
+
+pub use crate::diagnostic::{Diagnostic, Severity, Span};
+pub use crate::error::TranslateError;
+pub use crate::instruction::VmInstruction;
+pub use crate::translator::{SizeReport, Source, TranslateOptions, Translation, Translator};
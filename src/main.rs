@@ -1,51 +1,799 @@
-mod constants;
-mod operations;
-mod parser;
+#![allow(clippy::needless_return)]
 
-use crate::operations::*;
+use hack_vm_translator::assembler;
+use hack_vm_translator::differential;
+use hack_vm_translator::emulator::{self, Cpu};
+use hack_vm_translator::formatter;
+use hack_vm_translator::interpreter::VmInterpreter;
+use hack_vm_translator::manifest::{Manifest, OutputFormat};
+use hack_vm_translator::test_script;
+use hack_vm_translator::{constants, parser, Diagnostic, Severity, TranslateError, TranslateOptions};
 
-use clap::Parser;
+use std::env;
+use std::fs::create_dir_all;
+use std::io::{self, IsTerminal};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+/// Kind of file written to the output path
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Emit {
+    /// Hack assembly
+    Asm,
+    /// Hack machine code as binary text
+    Hack
+}
+
+impl Emit {
+    fn extension(&self) -> &'static str {
+        match self {
+            Emit::Asm   => "asm",
+            Emit::Hack  => "hack"
+        }
+    }
+}
+
+/// When to colour diagnostics
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ColorChoice {
+    /// Colour when printing to a terminal and NO_COLOR isn't set
+    Auto,
+    Always,
+    Never
+}
 
 #[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Cli {
-    /// Input file/directory path
+    #[command(subcommand)]
+    command: Option<Commands>,
+    #[command(flatten)]
+    translate: TranslateArgs,
+    #[command(flatten)]
+    global: GlobalArgs,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Translate VM code to Hack assembly or machine code (the default command)
+    Translate(TranslateArgs),
+    /// Assemble Hack assembly into machine code
+    Assemble(AssembleArgs),
+    /// Run a program on the Hack CPU emulator
+    Run(RunArgs),
+    /// Check the translated program against the VM interpreter
+    Verify(VerifyArgs),
+    /// Run nand2tetris .tst scripts and compare their output with the .cmp files
+    Test(TestArgs),
+    /// Report errors and warnings in VM code without writing any output
+    Check(CheckArgs),
+    /// Format VM files in place
+    Fmt(FmtArgs),
+}
+
+// Output settings shared by every command
+#[derive(Args)]
+struct GlobalArgs {
+    /// Print more about what is being done
+    #[arg(short, long, global = true, conflicts_with = "quiet")]
+    verbose: bool,
+    /// Only print errors and requested output
+    #[arg(short, long, global = true)]
+    quiet: bool,
+    /// When to colour diagnostics
+    #[arg(long, value_enum, global = true, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+}
+
+// Arguments for translating to an output file
+#[derive(Args)]
+struct TranslateArgs {
+    /// Input files/directories, linked into one program (- reads stdin, a last path ending in .asm or .hack is the output path)
+    #[arg(required = true)]
+    input_paths: Vec<String>,
+    /// Output file path (- writes to stdout, the default for stdin)
+    #[arg(short, long, value_name = "PATH")]
+    output: Option<String>,
+    #[command(flatten)]
+    project: ProjectArgs,
+    #[command(flatten)]
+    options: OptionArgs,
+    /// Kind of output to write (default asm)
+    #[arg(long, value_enum)]
+    emit: Option<Emit>,
+    /// Print how many ROM instructions each file and function takes up
+    #[arg(long)]
+    size_report: bool,
+    /// Also write a listing of each VM command's ROM addresses and instructions (.lst)
+    #[arg(long)]
+    listing: bool,
+    /// Also write a JSON source map from the assembly back to the VM code (.map.json)
+    #[arg(long)]
+    source_map: bool,
+}
+
+// Arguments for assembling to a .hack file
+#[derive(Args)]
+struct AssembleArgs {
+    /// Assembly file to assemble (.asm, - reads stdin)
     input_path: String,
-    /// Output file path
-    output_path: Option<String>,
+    /// Output file path (default: the input with a .hack extension, stdout for stdin)
+    #[arg(short, long, value_name = "PATH")]
+    output: Option<String>,
+}
+
+// Arguments for running a program on the emulator
+#[derive(Args)]
+struct RunArgs {
+    /// Program to run (.vm file or directory, .asm or .hack)
+    input_path: String,
+    /// Maximum number of instructions to execute
+    #[arg(long, default_value_t = 1_000_000)]
+    cycles: u64,
+    /// RAM range to print once the program stops (ex. 0, 256..260)
+    #[arg(long, value_name = "RANGE", value_parser = parse_range)]
+    dump: Vec<Range<u16>>,
+    /// Set a RAM word before running (ex. 0=256)
+    #[arg(long, value_name = "ADDRESS=VALUE", value_parser = parse_assignment)]
+    set: Vec<(u16, i16)>,
+    /// Execute VM code directly on the reference VM interpreter instead of the emulator
+    #[arg(long)]
+    interpret: bool,
+    #[command(flatten)]
+    options: OptionArgs,
+}
+
+// Arguments for comparing the translated program with the VM interpreter
+#[derive(Args)]
+struct VerifyArgs {
+    /// VM program to check (.vm file or directory)
+    input_path: String,
+    /// Maximum number of VM commands to execute
+    #[arg(long, default_value_t = 1_000_000)]
+    steps: u64,
+    /// Set a RAM word before running (ex. 0=256)
+    #[arg(long, value_name = "ADDRESS=VALUE", value_parser = parse_assignment)]
+    set: Vec<(u16, i16)>,
+    #[command(flatten)]
+    options: OptionArgs,
+}
+
+// Arguments for running test scripts
+#[derive(Args)]
+struct TestArgs {
+    /// Test scripts to run (.tst)
+    #[arg(required = true)]
+    scripts: Vec<String>,
+    #[command(flatten)]
+    options: OptionArgs,
+}
+
+// Arguments for checking VM code without writing output
+#[derive(Args)]
+struct CheckArgs {
+    /// Input files/directories, linked into one program (- reads stdin)
+    #[arg(required = true)]
+    input_paths: Vec<String>,
+    #[command(flatten)]
+    project: ProjectArgs,
+    #[command(flatten)]
+    options: OptionArgs,
+}
+
+// Arguments for formatting VM files
+#[derive(Args)]
+struct FmtArgs {
+    /// VM files or directories of them to format (- formats stdin to stdout)
+    #[arg(required = true)]
+    paths: Vec<String>,
+    /// Format the vm files in subdirectories of directories too
+    #[arg(short, long)]
+    recursive: bool,
+    /// List the files that aren't formatted instead of changing them, failing if there are any
+    #[arg(long)]
+    check: bool,
+}
+
+// How the vm files of a project given on the command line are found and ordered
+#[derive(Args)]
+struct ProjectArgs {
+    /// Look for vm files in subdirectories of input directories too
+    #[arg(short, long)]
+    recursive: bool,
+    /// Translate this file before all the others (ex. Sys.vm or Sys)
+    #[arg(long, value_name = "FILE")]
+    first: Option<String>,
+    /// Module name for the static variables of VM code read from stdin (default Main)
+    #[arg(long, value_name = "MODULE")]
+    name: Option<String>,
+}
+
+impl ProjectArgs {
+    fn project_options(&self) -> parser::ProjectOptions {
+        return parser::ProjectOptions {
+            recursive: self.recursive,
+            first: self.first.clone(),
+            name: self.name.clone()
+        };
+    }
+}
+
+// Translation settings shared by every command that translates VM code
+#[derive(Args)]
+struct OptionArgs {
+    /// Always write the bootstrap code (default for directories and several inputs)
+    #[arg(long, conflicts_with = "no_bootstrap")]
+    bootstrap: bool,
+    /// Never write the bootstrap code (default for single files)
+    #[arg(long)]
+    no_bootstrap: bool,
+    /// Stack pointer value set by the bootstrap code
+    #[arg(long, value_name = "ADDRESS", default_value_t = constants::DEFAULT_SP,
+        value_parser = clap::value_parser!(u16).range(0..=constants::MAX_CONSTANT as i64))]
+    sp: u16,
+    /// Function called by the bootstrap code (default Sys.init)
+    #[arg(long, value_name = "FUNCTION")]
+    entry: Option<String>,
+    /// Remove redundant instructions from the generated assembly
+    #[arg(short = 'O', long)]
+    optimize: bool,
+    /// Share comparison, call and return code between commands to make the program smaller
+    #[arg(long)]
+    optimize_size: bool,
+}
+
+impl OptionArgs {
+    /// Translation settings chosen on the command line, falling back to the project manifest
+    fn translate_options(&self, manifest: Option<&Manifest>) -> TranslateOptions {
+        let bootstrap = if self.bootstrap {
+            Some(true)
+        }
+        else if self.no_bootstrap {
+            Some(false)
+        }
+        else {
+            manifest.and_then(|manifest| manifest.bootstrap)
+        };
+
+        let entry_point = self.entry.clone()
+            .or_else(|| manifest.and_then(|manifest| manifest.entry.clone()))
+            .unwrap_or(constants::DEFAULT_ENTRY_POINT.to_owned());
+
+        let optimization = manifest.and_then(|manifest| manifest.optimize);
+
+        return TranslateOptions {
+            bootstrap,
+            initial_sp: self.sp,
+            entry_point,
+            optimize: self.optimize || optimization.is_some_and(|level| level.peephole()),
+            optimize_size: self.optimize_size || optimization.is_some_and(|level| level.size()),
+            executable: false
+        };
+    }
+}
+
+/// Parses a RAM address or a range of them (ex. 5 or 256..260)
+fn parse_range(s: &str) -> Result<Range<u16>, String> {
+    match s.split_once("..") {
+        Some((start, end))  => {
+            let start = parse_address(start)?;
+            // The end is exclusive, so it may be one past the last address
+            let end = match end.trim().parse::<u16>() {
+                Ok(end) if end as usize <= emulator::RAM_SIZE   => end,
+                _                                               => return Err(format!("'{}' is not a RAM address (0-{})", end, emulator::RAM_SIZE))
+            };
+            Ok(start..end)
+        },
+        None                => {
+            let addr = parse_address(s)?;
+            Ok(addr..addr + 1)
+        }
+    }
+}
+
+/// Parses an address below the size of the RAM
+fn parse_address(s: &str) -> Result<u16, String> {
+    return match s.trim().parse::<u16>() {
+        Ok(addr) if (addr as usize) < emulator::RAM_SIZE    => Ok(addr),
+        _                                                   => Err(format!("'{}' is not a RAM address (0-{})", s, emulator::RAM_SIZE - 1))
+    };
+}
+
+/// Parses an ADDRESS=VALUE RAM assignment
+fn parse_assignment(s: &str) -> Result<(u16, i16), String> {
+    let (addr, value) = s.split_once('=').ok_or(format!("'{}' is not of the form ADDRESS=VALUE", s))?;
+    let addr = parse_address(addr)?;
+    let value = value.trim().parse().map_err(|_| format!("'{}' is not a 16 bit value", value))?;
+
+    return Ok((addr, value));
+}
+
+/// Prints diagnostics and messages as chosen by the global flags
+struct Reporter {
+    verbose: bool,
+    quiet: bool,
+    color: bool
+}
+
+impl Reporter {
+    fn new(args: &GlobalArgs) -> Self {
+        let color = match args.color {
+            ColorChoice::Always => true,
+            ColorChoice::Never  => false,
+            ColorChoice::Auto   => io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none()
+        };
+
+        return Reporter { verbose: args.verbose, quiet: args.quiet, color };
+    }
+
+    /// Prints every diagnostic, leaving out warnings when quiet
+    fn print(&self, diagnostics: &[Diagnostic]) {
+        for diagnostic in diagnostics.iter() {
+            if self.quiet && !diagnostic.is_error() {
+                continue;
+            }
+
+            let severity = self.severity(diagnostic.severity);
+
+            match (&diagnostic.span, self.color) {
+                (Some(span), true)  => eprintln!("\x1b[1m{}:\x1b[0m {}: {}", span, severity, diagnostic.error),
+                (None, true)        => eprintln!("{}: {}", severity, diagnostic.error),
+                (_, false)          => eprintln!("{}", diagnostic)
+            }
+        }
+    }
+
+    /// Prints every diagnostic and exits if any of them is an error
+    fn report(&self, diagnostics: &[Diagnostic]) {
+        self.print(diagnostics);
+
+        let error_count = diagnostics.iter().filter(|d| d.is_error()).count();
+
+        if error_count > 0 {
+            self.fail(&format!("failed with {} error(s)", error_count));
+        }
+    }
+
+    /// Prints an error message and exits
+    fn fail(&self, message: &str) -> ! {
+        eprintln!("{}: {}", self.severity(Severity::Error), message);
+        process::exit(1);
+    }
+
+    /// Prints the result of a command unless quiet
+    fn status(&self, message: &str) {
+        if !self.quiet {
+            println!("{}", message);
+        }
+    }
+
+    /// Prints a progress message when verbose
+    fn info(&self, message: &str) {
+        if self.verbose {
+            eprintln!("{}", message);
+        }
+    }
+
+    /// Severity, coloured like gcc's when colour is on
+    fn severity(&self, severity: Severity) -> String {
+        if !self.color {
+            return severity.to_string();
+        }
+
+        let code = match severity {
+            Severity::Error     => "1;31",
+            Severity::Warning   => "1;35"
+        };
+
+        return format!("\x1b[{}m{}\x1b[0m", code, severity);
+    }
 }
 
 fn main() {
     let args = Cli::parse();
+    let reporter = Reporter::new(&args.global);
+
+    match args.command {
+        Some(Commands::Translate(translate_args)) => translate(translate_args, &reporter),
+        Some(Commands::Assemble(assemble_args)) => assemble(assemble_args, &reporter),
+        Some(Commands::Run(run_args)) => run(run_args, &reporter),
+        Some(Commands::Verify(verify_args)) => verify(verify_args, &reporter),
+        Some(Commands::Test(test_args)) => test(test_args, &reporter),
+        Some(Commands::Check(check_args)) => check(check_args, &reporter),
+        Some(Commands::Fmt(fmt_args)) => format_files(fmt_args, &reporter),
+        None => translate(args.translate, &reporter)
+    }
+}
+
+/// Reads the manifest of a project given as a directory, None if it has none
+fn find_manifest(input_path: &str, reporter: &Reporter) -> Option<Manifest> {
+    if !Path::new(input_path).is_dir() {
+        return None;
+    }
+
+    match Manifest::find(input_path) {
+        Ok(manifest) => manifest,
+        Err(diagnostic) => {
+            reporter.report(&[diagnostic]);
+            None
+        }
+    }
+}
+
+/// Translates the input and writes the requested output file
+fn translate(args: TranslateArgs, reporter: &Reporter) {
+    let mut input_paths = args.input_paths;
+    let mut output = args.output;
+
+    // Output path given the old way, as the last path after the inputs
+    let is_output = |path: &str| {
+        Path::new(path).extension().is_some_and(|ext| ext == "asm" || ext == "hack")
+    };
+    if output.is_none() && input_paths.len() >= 2 && is_output(input_paths.last().unwrap()) {
+        output = input_paths.pop();
+    }
 
-    let mut parser = parser::Parser::new(&args.input_path);
+    let input_path = input_paths[0].clone();
 
-    // Get the default name (input_path with .asm)
+    // Settings for a project given as one directory can come from its manifest
+    let manifest = if input_paths.len() == 1 { find_manifest(&input_path, reporter) } else { None };
+    let formats = manifest.as_ref().map_or(Vec::new(), |manifest| manifest.emit.clone());
 
-    let default_name;
-    
-    if args.input_path.contains(".vm") {
-        default_name = args.input_path.replace(".vm", ".asm");
+    let mut emits: Vec<Emit> = match args.emit {
+        Some(emit)  => vec![emit],
+        None        => formats.iter().filter_map(|format| match format {
+            OutputFormat::Asm   => Some(Emit::Asm),
+            OutputFormat::Hack  => Some(Emit::Hack),
+            _                   => None
+        }).collect()
+    };
+    if emits.is_empty() {
+        emits.push(Emit::Asm);
     }
-    else {
-        let unix_path = args.input_path.replace("\\", "/").to_owned();
 
-        let name_stripped = unix_path.split("/").collect::<Vec<&str>>();
+    // Machine code is going to be run, so it can't call functions that don't exist
+    let mut options = args.options.translate_options(manifest.as_ref());
+    options.executable = emits.contains(&Emit::Hack);
+
+    let mut parser = match parser::Parser::with_paths(&input_paths, args.project.project_options(), options) {
+        Ok(parser) => parser,
+        Err(diagnostics) => {
+            reporter.report(&diagnostics);
+            return;
+        }
+    };
+
+    reporter.report(parser.diagnostics());
+
+    reporter.info(&format!("Translated {} file(s) in order:", parser.files().len()));
+    for file in parser.files().iter() {
+        reporter.info(&format!("  {}", file));
+    }
+
+    let target = manifest.as_ref().and_then(|manifest| manifest.target.as_ref().map(|target| manifest.resolve(target)));
+
+    if let Some(dir) = target.as_ref().and_then(|target| target.parent()) {
+        if let Err(e) = create_dir_all(dir) {
+            reporter.report(&[TranslateError::io(&dir.to_string_lossy(), e).into()]);
+        }
+    }
 
-        let mut base_name = "";
+    // -o (or stdin going to stdout) names the output exactly, otherwise each kind of file gets its own extension
+    let exact = emits.len() == 1 && (output.is_some() || input_path == constants::STDIO_PATH);
+    let output_path = match (output, target) {
+        (Some(output), _)       => PathBuf::from(output),
+        (None, Some(target))    => target,
+        (None, None)            => default_output_path(&input_path, emits[0].extension())
+    };
 
-        for word in name_stripped.iter().rev() {
-            if word != &"" {
-                base_name = word;
-                break;
+    let to_stdout = output_path == Path::new(constants::STDIO_PATH);
+    if to_stdout && (args.listing || args.source_map) {
+        reporter.fail("--listing and --source-map need an output file to be named after, not stdout");
+    }
+
+    for emit in emits.iter() {
+        let emit_path = if exact { output_path.clone() } else { output_path.with_extension(emit.extension()) };
+        let emit_path = emit_path.to_string_lossy();
+
+        let result = match emit {
+            Emit::Asm   => parser.output(&emit_path).map_err(|err| vec![err.into()]),
+            Emit::Hack  => parser.output_hack(&emit_path)
+        };
+
+        if let Err(diagnostics) = result {
+            reporter.report(&diagnostics);
+        }
+    }
+
+    if args.listing || formats.contains(&OutputFormat::Listing) {
+        let listing_path = output_path.with_extension("lst");
+
+        if let Err(err) = parser.output_listing(&listing_path.to_string_lossy()) {
+            reporter.report(&[err.into()]);
+        }
+    }
+
+    if args.source_map || formats.contains(&OutputFormat::SourceMap) {
+        let map_path = output_path.with_extension("map.json");
+
+        if let Err(err) = parser.output_source_map(&map_path.to_string_lossy()) {
+            reporter.report(&[err.into()]);
+        }
+    }
+
+    // Keep the report out of the program when it's written to stdout
+    if args.size_report && to_stdout {
+        eprint!("{}", parser.translation().size_report());
+    }
+    else if args.size_report {
+        print!("{}", parser.translation().size_report());
+    }
+}
+
+/// Output path used when none is given
+/// 
+/// A file gets the extension swapped (Main.vm to Main.asm), a directory gets a file named after
+/// it inside of it (Prog/ to Prog/Prog.asm) and stdin goes to stdout
+fn default_output_path(input_path: &str, extension: &str) -> PathBuf {
+    if input_path == constants::STDIO_PATH {
+        return PathBuf::from(constants::STDIO_PATH);
+    }
+
+    let path = Path::new(input_path);
+
+    if !path.is_dir() {
+        return path.with_extension(extension);
+    }
+
+    // . and .. have no name of their own, so use the name of the directory they point to
+    let dir_name = match path.file_name() {
+        Some(name)  => Some(name.to_owned()),
+        None        => path.canonicalize().ok().and_then(|path| path.file_name().map(|name| name.to_owned()))
+    };
+    let dir_name = dir_name.map_or("out".to_owned(), |name| name.to_string_lossy().into_owned());
+
+    return path.join(format!("{}.{}", dir_name, extension));
+}
+
+/// Runs the input on the emulator and prints the requested RAM
+fn run(args: RunArgs, reporter: &Reporter) {
+    if args.interpret {
+        interpret(args, reporter);
+        return;
+    }
+
+    let options = args.options.translate_options(find_manifest(&args.input_path, reporter).as_ref());
+
+    let rom = match emulator::load_rom(&args.input_path, options) {
+        Ok(rom) => rom,
+        Err(diagnostics) => {
+            reporter.report(&diagnostics);
+            return;
+        }
+    };
+
+    let mut cpu = Cpu::new(rom);
+
+    for (addr, value) in args.set.iter() {
+        cpu.set_ram(*addr, *value);
+    }
+
+    let reason = cpu.run(args.cycles);
+    reporter.status(&format!("Stopped after {} cycles at PC {}: {}", cpu.cycles(), cpu.pc(), reason));
+
+    for range in args.dump.iter() {
+        for addr in range.clone() {
+            println!("RAM[{}] = {}", addr, cpu.ram()[addr as usize]);
+        }
+    }
+}
+
+/// Runs the input on the VM interpreter and prints the requested RAM
+fn interpret(args: RunArgs, reporter: &Reporter) {
+    let mut options = args.options.translate_options(find_manifest(&args.input_path, reporter).as_ref());
+    options.executable = true;
+
+    let parser = match parser::Parser::new(&args.input_path, options) {
+        Ok(parser) => parser,
+        Err(diagnostics) => {
+            reporter.report(&diagnostics);
+            return;
+        }
+    };
+
+    reporter.report(parser.diagnostics());
+
+    let mut interpreter = VmInterpreter::new(parser.translation());
+
+    for (addr, value) in args.set.iter() {
+        interpreter.set_ram(*addr, *value);
+    }
+
+    match interpreter.run(args.cycles) {
+        Ok(reason) => reporter.status(&format!("Stopped after {} steps at command {}: {}", interpreter.steps(), interpreter.pc(), reason)),
+        Err(diagnostic) => {
+            reporter.report(&[diagnostic]);
+            return;
+        }
+    }
+
+    for range in args.dump.iter() {
+        for addr in range.clone() {
+            println!("RAM[{}] = {}", addr, interpreter.ram()[addr as usize]);
+        }
+    }
+}
+
+/// Runs the input on the emulator and the VM interpreter and reports where they first differ
+fn verify(args: VerifyArgs, reporter: &Reporter) {
+    let mut options = args.options.translate_options(find_manifest(&args.input_path, reporter).as_ref());
+    options.executable = true;
+
+    let parser = match parser::Parser::new(&args.input_path, options) {
+        Ok(parser) => parser,
+        Err(diagnostics) => {
+            reporter.report(&diagnostics);
+            return;
+        }
+    };
+
+    reporter.report(parser.diagnostics());
+
+    let comparison = match differential::compare(parser.translation(), &args.set, args.steps) {
+        Ok(comparison) => comparison,
+        Err(diagnostics) => {
+            reporter.report(&diagnostics);
+            return;
+        }
+    };
+
+    if let Some(divergence) = comparison.divergence {
+        eprintln!("{}", divergence);
+        reporter.fail(&format!("translated program diverged after {} steps", comparison.steps));
+    }
+
+    reporter.status(&format!("Matched for {} steps ({} cycles): {}", comparison.steps, comparison.cycles, comparison.stop_reason));
+}
+
+/// Runs every test script, reporting each failure, and exits if any of them failed
+fn test(args: TestArgs, reporter: &Reporter) {
+    let mut failed = 0;
+
+    for script in args.scripts.iter() {
+        match test_script::run_script(script, args.options.translate_options(None)) {
+            Ok(result) => {
+                reporter.print(&result.diagnostics);
+                for message in result.messages.iter() {
+                    println!("{}", message);
+                }
+
+                if result.compare_path.is_some() {
+                    reporter.status(&format!("{}: comparison ended successfully", script));
+                }
+                else {
+                    reporter.status(&format!("{}: end of script", script));
+                }
+            },
+            Err(diagnostics) => {
+                reporter.print(&diagnostics);
+                println!("{}: failed", script);
+                failed += 1;
+            }
+        }
+    }
+
+    if failed > 0 {
+        reporter.fail(&format!("{} of {} test script(s) failed", failed, args.scripts.len()));
+    }
+}
+
+/// Assembles a .asm file and writes the machine code
+fn assemble(args: AssembleArgs, reporter: &Reporter) {
+    let asm = match parser::read_input(&args.input_path) {
+        Ok(asm) => asm,
+        Err(err) => {
+            reporter.report(&[err.into()]);
+            return;
+        }
+    };
+
+    let lines: Vec<&str> = asm.lines().collect();
+    let name = if args.input_path == constants::STDIO_PATH { constants::STDIN_NAME } else { &args.input_path };
+
+    let machine_code = match assembler::assemble(name, &lines) {
+        Ok(machine_code) => machine_code,
+        Err(diagnostics) => {
+            reporter.report(&diagnostics);
+            return;
+        }
+    };
+
+    let output_path = args.output.unwrap_or_else(|| {
+        default_output_path(&args.input_path, "hack").to_string_lossy().into_owned()
+    });
+
+    if let Err(err) = parser::write_output(&output_path, &machine_code.to_hack_string()) {
+        reporter.report(&[err.into()]);
+    }
+
+    reporter.info(&format!("Assembled {} instruction(s) into {}", machine_code.instructions.len(), output_path));
+}
+
+/// Translates the input to report its errors and warnings, without writing any output
+fn check(args: CheckArgs, reporter: &Reporter) {
+    let manifest = if args.input_paths.len() == 1 { find_manifest(&args.input_paths[0], reporter) } else { None };
+
+    let options = args.options.translate_options(manifest.as_ref());
+
+    let parser = match parser::Parser::with_paths(&args.input_paths, args.project.project_options(), options) {
+        Ok(parser) => parser,
+        Err(diagnostics) => {
+            reporter.report(&diagnostics);
+            return;
+        }
+    };
+
+    reporter.report(parser.diagnostics());
+    reporter.info(&format!("Checked {} file(s) with {} warning(s)", parser.files().len(), parser.diagnostics().len()));
+}
+
+/// Formats every vm file given, or lists the ones that aren't formatted with --check
+fn format_files(args: FmtArgs, reporter: &Reporter) {
+    let mut files = Vec::new();
+    let mut diagnostics = Vec::new();
+
+    for path in args.paths.iter() {
+        if path == constants::STDIO_PATH || !Path::new(path).is_dir() {
+            files.push(path.clone());
+        }
+        else if let Err(diagnostic) = parser::find_vm_files(path, args.recursive, &mut files, &mut diagnostics) {
+            diagnostics.push(diagnostic);
+        }
+    }
+
+    let mut unformatted = 0;
+
+    for file in files.iter() {
+        let vm_code = match parser::read_input(file) {
+            Ok(vm_code) => vm_code,
+            Err(err) => {
+                diagnostics.push(err.into());
+                continue;
+            }
+        };
+
+        let formatted = formatter::format_vm(&vm_code);
+
+        // stdin always goes back out to stdout, formatted or not
+        if file == constants::STDIO_PATH && !args.check {
+            if let Err(err) = parser::write_output(file, &formatted) {
+                diagnostics.push(err.into());
             }
+            continue;
         }
 
-        default_name = format!("{}/{}.asm", args.input_path, base_name);
+        if formatted == vm_code {
+            continue;
+        }
+
+        if args.check {
+            println!("{}", file);
+            unformatted += 1;
+        }
+        else if let Err(err) = parser::write_output(file, &formatted) {
+            diagnostics.push(err.into());
+        }
+        else {
+            reporter.info(&format!("Formatted {}", file));
+        }
     }
 
-    match args.output_path {
-        Some(output) => parser.output(&output),
-        None => parser.output(&default_name)
+    reporter.report(&diagnostics);
+
+    if unformatted > 0 {
+        reporter.fail(&format!("{} of {} file(s) are not formatted", unformatted, files.len()));
     }
-    
 }
@@ -0,0 +1,229 @@
+use std::fs::read_to_string;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::diagnostic::{Diagnostic, Span};
+use crate::error::TranslateError;
+
+/// File name of the project manifest looked for in input directories
+pub const MANIFEST_NAME: &str = "vmproject.toml";
+
+/// How much to optimize the generated assembly
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Optimization {
+    /// Translate each command on its own
+    None,
+    /// Remove redundant instructions (-O)
+    Peephole,
+    /// Share code between commands (--optimize-size)
+    Size,
+    /// Both of the above
+    All
+}
+
+impl Optimization {
+    /// Whether the peephole optimizer runs
+    pub fn peephole(&self) -> bool {
+        return *self == Optimization::Peephole || *self == Optimization::All;
+    }
+
+    /// Whether code is shared between commands
+    pub fn size(&self) -> bool {
+        return *self == Optimization::Size || *self == Optimization::All;
+    }
+}
+
+/// Kind of file written for the project
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    /// Hack assembly (.asm)
+    Asm,
+    /// Hack machine code as binary text (.hack)
+    Hack,
+    /// ROM addresses and instructions of each command (.lst)
+    Listing,
+    /// JSON source map (.map.json)
+    SourceMap
+}
+
+/// Project settings read from a vmproject.toml
+/// 
+/// Paths are relative to the manifest's directory
+/// 
+/// Ex:
+/// sources = ["src", "os"]
+/// order = ["src/Sys.vm"]
+/// entry = "Main.main"
+/// optimize = "size"
+/// emit = ["hack", "listing"]
+/// target = "build/Game"
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    /// Directories holding the vm files, the manifest's own directory if empty
+    #[serde(default)]
+    pub sources: Vec<Spanned<String>>,
+    /// Whether to look for vm files in subdirectories of the sources too
+    #[serde(default)]
+    pub recursive: bool,
+    /// Files translated before the rest and in this order
+    #[serde(default)]
+    pub order: Vec<Spanned<String>>,
+    /// Function called by the bootstrap code
+    pub entry: Option<String>,
+    /// Whether to write the bootstrap code
+    pub bootstrap: Option<bool>,
+    pub optimize: Option<Optimization>,
+    /// Kinds of file to write
+    #[serde(default)]
+    pub emit: Vec<OutputFormat>,
+    /// Output path, with each kind of file getting its own extension
+    pub target: Option<String>,
+    /// Path the manifest was read from
+    #[serde(skip)]
+    pub path: String,
+    /// Text of the manifest, used to point diagnostics at an entry
+    #[serde(skip)]
+    text: String
+}
+
+impl Manifest {
+    /// Reads the manifest in a directory, None if it doesn't have one
+    pub fn find(dir_path: &str) -> Result<Option<Manifest>, Diagnostic> {
+        let path = Path::new(dir_path).join(MANIFEST_NAME);
+
+        if !path.is_file() {
+            return Ok(None);
+        }
+
+        let path = path.to_string_lossy().into_owned();
+        let text = read_to_string(&path).map_err(|e| TranslateError::io(&path, e))?;
+
+        return Manifest::parse(&path, &text).map(Some);
+    }
+
+    /// Parses the text of a manifest, path is only used in diagnostics
+    pub fn parse(path: &str, text: &str) -> Result<Manifest, Diagnostic> {
+        let mut manifest: Manifest = toml::from_str(text).map_err(|e| {
+            let span = e.span().map(|range| span_of(path, text, range));
+            Diagnostic::error(span, TranslateError::InvalidManifest(e.message().to_owned()))
+        })?;
+
+        manifest.path = path.to_owned();
+        manifest.text = text.to_owned();
+
+        return Ok(manifest);
+    }
+
+    /// Path of a file or directory named in the manifest
+    pub fn resolve(&self, relative: &str) -> PathBuf {
+        let dir = Path::new(&self.path).parent().unwrap_or(Path::new(""));
+
+        return dir.join(relative);
+    }
+
+    /// Location of a value in the manifest
+    pub fn span<T>(&self, value: &Spanned<T>) -> Span {
+        return span_of(&self.path, &self.text, value.span());
+    }
+}
+
+/// Converts a byte range of the text into a span on the line it starts on
+fn span_of(path: &str, text: &str, range: Range<usize>) -> Span {
+    let start = range.start.min(text.len());
+    let line_start = text[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line = text[..start].matches('\n').count() + 1;
+
+    let start_col = text[line_start..start].chars().count() + 1;
+    let end = range.end.clamp(start, text[start..].find('\n').map_or(text.len(), |i| start + i));
+    let end_col = start_col + text[start..end].chars().count();
+
+    return Span::new(path, line, start_col, end_col);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = "\
+sources = [\"src\", \"os\"]
+order = [\"src/Sys.vm\"]
+entry = \"Main.main\"
+bootstrap = true
+optimize = \"size\"
+emit = [\"hack\", \"source-map\"]
+target = \"build/Game\"
+";
+
+    #[test]
+    fn parses_every_setting() {
+        let manifest = Manifest::parse("game/vmproject.toml", MANIFEST).unwrap();
+
+        let sources: Vec<&str> = manifest.sources.iter().map(|source| source.get_ref().as_str()).collect();
+        assert_eq!(sources, vec!["src", "os"]);
+        assert_eq!(manifest.order[0].get_ref(), "src/Sys.vm");
+        assert_eq!(manifest.entry.as_deref(), Some("Main.main"));
+        assert_eq!(manifest.bootstrap, Some(true));
+        assert_eq!(manifest.optimize, Some(Optimization::Size));
+        assert_eq!(manifest.emit, vec![OutputFormat::Hack, OutputFormat::SourceMap]);
+        assert_eq!(manifest.target.as_deref(), Some("build/Game"));
+        assert!(!manifest.recursive);
+    }
+
+    #[test]
+    fn defaults_missing_settings() {
+        let manifest = Manifest::parse("vmproject.toml", "").unwrap();
+
+        assert!(manifest.sources.is_empty() && manifest.order.is_empty() && manifest.emit.is_empty());
+        assert_eq!(manifest.entry, None);
+        assert_eq!(manifest.optimize, None);
+    }
+
+    #[test]
+    fn points_at_entries() {
+        let manifest = Manifest::parse("game/vmproject.toml", MANIFEST).unwrap();
+
+        // The span covers the quoted string
+        assert_eq!(manifest.span(&manifest.order[0]), Span::new("game/vmproject.toml", 2, 10, 22));
+        assert_eq!(manifest.span(&manifest.sources[1]), Span::new("game/vmproject.toml", 1, 19, 23));
+    }
+
+    #[test]
+    fn resolves_paths_from_its_directory() {
+        let manifest = Manifest::parse("game/vmproject.toml", MANIFEST).unwrap();
+        assert_eq!(manifest.resolve("src/Sys.vm"), Path::new("game/src/Sys.vm"));
+
+        let manifest = Manifest::parse("vmproject.toml", MANIFEST).unwrap();
+        assert_eq!(manifest.resolve("src"), Path::new("src"));
+    }
+
+    #[test]
+    fn rejects_unknown_settings() {
+        let diagnostic = Manifest::parse("vmproject.toml", "entry = \"Sys.init\"\noptimise = \"size\"\n").unwrap_err();
+
+        assert!(matches!(&diagnostic.error, TranslateError::InvalidManifest(message) if message.contains("unknown field `optimise`")));
+        assert_eq!(diagnostic.span.as_ref().map(|span| span.line), Some(2));
+        assert_eq!(diagnostic.span.as_ref().map(|span| span.start_col), Some(1));
+    }
+
+    #[test]
+    fn rejects_unknown_values() {
+        let diagnostic = Manifest::parse("vmproject.toml", "emit = [\"asm\", \"elf\"]\n").unwrap_err();
+
+        assert!(matches!(&diagnostic.error, TranslateError::InvalidManifest(message) if message.contains("unknown variant `elf`")));
+        assert_eq!(diagnostic.span, Some(Span::new("vmproject.toml", 1, 16, 21)));
+    }
+
+    #[test]
+    fn keeps_spans_on_one_line() {
+        // A range running past the end of its line is cut at the newline
+        assert_eq!(span_of("m.toml", "a = 1\nbc = [\n2]\n", 6..14), Span::new("m.toml", 2, 1, 7));
+        // A range past the end of the text points just after it
+        assert_eq!(span_of("m.toml", "a = 1", 10..12), Span::new("m.toml", 1, 6, 6));
+    }
+}
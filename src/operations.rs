@@ -3,6 +3,8 @@ use std::str::FromStr;
 
 use enumset::{EnumSetType, EnumSet, enum_set};
 
+use crate::error::TranslateError;
+
 /// Operation being performed by a command
 #[derive(EnumSetType, Debug)]
 pub enum Operation {
@@ -33,7 +35,7 @@ pub enum Operation {
 }
 
 impl FromStr for Operation {
-    type Err = ();
+    type Err = TranslateError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             ""          => Ok(Operation::Noop),
@@ -54,7 +56,33 @@ impl FromStr for Operation {
             "function"  => Ok(Operation::Function),
             "call"      => Ok(Operation::Call),
             "return"    => Ok(Operation::Return),
-            _           => panic!("Read command ({}) is not a supported operation", s)
+            _           => Err(TranslateError::UnknownOperation(s.to_owned()))
+        }
+    }
+}
+
+impl Operation {
+    /// Keyword used for the operation in VM code
+    pub fn vm_name(&self) -> &'static str {
+        match self {
+            Operation::Add      => "add",
+            Operation::Sub      => "sub",
+            Operation::Neg      => "neg",
+            Operation::Eq       => "eq",
+            Operation::Gt       => "gt",
+            Operation::Lt       => "lt",
+            Operation::And      => "and",
+            Operation::Or       => "or",
+            Operation::Not      => "not",
+            Operation::Label    => "label",
+            Operation::Goto     => "goto",
+            Operation::IfGoto   => "if-goto",
+            Operation::Push     => "push",
+            Operation::Pop      => "pop",
+            Operation::Function => "function",
+            Operation::Call     => "call",
+            Operation::Return   => "return",
+            _                   => ""
         }
     }
 }
@@ -86,7 +114,7 @@ pub enum Segment {
 }
 
 impl FromStr for Segment {
-    type Err = ();
+    type Err = TranslateError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             ""          => Ok(Segment::None),
@@ -99,7 +127,25 @@ impl FromStr for Segment {
             "static"    => Ok(Segment::Static),
             "pointer"   => Ok(Segment::Pointer),
             "temp"      => Ok(Segment::Temp),
-            _           => panic!("No segment matching {}", s)
+            _           => Err(TranslateError::UnknownSegment(s.to_owned()))
+        }
+    }
+}
+
+impl Segment {
+    /// Keyword used for the segment in VM code
+    pub fn vm_name(&self) -> &'static str {
+        match self {
+            Segment::Sp         => "sp",
+            Segment::Local      => "local",
+            Segment::Argument   => "argument",
+            Segment::This       => "this",
+            Segment::That       => "that",
+            Segment::Constant   => "constant",
+            Segment::Static     => "static",
+            Segment::Pointer    => "pointer",
+            Segment::Temp       => "temp",
+            _                   => ""
         }
     }
 }
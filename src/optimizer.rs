@@ -0,0 +1,254 @@
+use std::collections::HashSet;
+
+use crate::assembler;
+use crate::parser::Command;
+
+/// push_d followed by pop_d, which leaves D as it was
+const PUSH_POP_D: [&str; 7] = ["@SP", "M=M+1", "A=M-1", "M=D", "@SP", "AM=M-1", "D=M"];
+/// Decrementing then incrementing SP
+const DEC_INC_SP: [&str; 4] = ["@SP", "M=M-1", "@SP", "M=M+1"];
+/// Incrementing SP then popping, which only has to point A at the old top of the stack
+const INC_POP_SP: [&str; 4] = ["@SP", "M=M+1", "@SP", "AM=M-1"];
+
+/// Line of the whole program being optimized
+struct Line {
+    /// Index of the command the line was generated for
+    command: usize,
+    /// None once the line has been removed
+    text: Option<String>
+}
+
+/// Removes redundant instructions from the generated assembly, including across command boundaries
+/// 
+/// Patterns never span a label since code could jump into the middle of them
+/// 
+/// Returns the index of every command whose code was fused with the command after it, so at the
+/// boundary between them the program isn't in the state the VM code would be
+pub(crate) fn optimize(commands: &mut [Command]) -> HashSet<usize> {
+    let mut lines: Vec<Line> = Vec::new();
+    // Indices into lines of the instructions since the last label
+    let mut code: Vec<usize> = Vec::new();
+    let mut fused = HashSet::new();
+
+    for (command_i, command) in commands.iter().enumerate() {
+        for text in command.get_processed().into_iter().flatten() {
+            let is_label = text.trim_start().starts_with('(');
+            let is_a_instruction = text.trim_start().starts_with('@');
+
+            // Patterns that change A are only removed when the next instruction loads A again
+            if is_label || is_a_instruction {
+                remove_ending(&mut lines, &mut code, &mut fused, &PUSH_POP_D);
+                remove_ending(&mut lines, &mut code, &mut fused, &DEC_INC_SP);
+            }
+
+            // Loading A twice in a row, the first one is never used
+            if is_a_instruction && code.last().is_some_and(|&i| is_a(&lines[i])) {
+                let last = code.pop().unwrap();
+                join(&mut fused, lines[last].command, command_i);
+                lines[last].text = None;
+            }
+
+            lines.push(Line { command: command_i, text: Some(text.clone()) });
+
+            if is_label {
+                code.clear();
+            }
+            else if assembler::is_instruction(text) {
+                code.push(lines.len() - 1);
+            }
+
+            if ends_with(&lines, &code, &INC_POP_SP) {
+                let start = code.len() - INC_POP_SP.len();
+                join(&mut fused, lines[code[start]].command, command_i);
+
+                // @SP M=M+1 @SP AM=M-1 -> @SP A=M
+                lines[code[start + 1]].text = None;
+                lines[code[start + 2]].text = None;
+                lines[code[start + 3]].text = Some("A=M".to_owned());
+
+                let rewritten = code[start + 3];
+                code.truncate(start + 1);
+                code.push(rewritten);
+            }
+        }
+    }
+
+    remove_ending(&mut lines, &mut code, &mut fused, &PUSH_POP_D);
+    remove_ending(&mut lines, &mut code, &mut fused, &DEC_INC_SP);
+
+    // Give each command back what is left of its code
+    let mut processed: Vec<Vec<String>> = commands.iter().map(|_| Vec::new()).collect();
+    for line in lines {
+        if let Some(text) = line.text {
+            processed[line.command].push(text);
+        }
+    }
+
+    for (command, asm) in commands.iter_mut().zip(processed) {
+        if command.has_command() {
+            command.replace_processed(asm);
+        }
+    }
+
+    return fused;
+}
+
+fn is_a(line: &Line) -> bool {
+    return line.text.as_deref().is_some_and(|text| text.trim_start().starts_with('@'));
+}
+
+/// Checks if the instructions since the last label end with pattern
+fn ends_with(lines: &[Line], code: &[usize], pattern: &[&str]) -> bool {
+    if code.len() < pattern.len() {
+        return false;
+    }
+
+    let tail = &code[code.len() - pattern.len()..];
+
+    return tail.iter().zip(pattern.iter()).all(|(&i, expected)| {
+        lines[i].text.as_deref().is_some_and(|text| assembler::strip_comment(text) == *expected)
+    });
+}
+
+/// Removes pattern if the instructions since the last label end with it
+fn remove_ending(lines: &mut [Line], code: &mut Vec<usize>, fused: &mut HashSet<usize>, pattern: &[&str]) {
+    if !ends_with(lines, code, pattern) {
+        return;
+    }
+
+    let start = code.len() - pattern.len();
+    join(fused, lines[code[start]].command, lines[*code.last().unwrap()].command);
+
+    for i in code.drain(start..) {
+        lines[i].text = None;
+    }
+}
+
+/// Marks every boundary from command first to command last as fused
+fn join(fused: &mut HashSet<usize>, first: usize, last: usize) {
+    fused.extend(first..last);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::differential;
+    use crate::emulator::StopReason;
+    use crate::translator::Translator;
+
+    /// Optimizes one command per slice of assembly, returning what is left of each and the fused commands
+    fn optimize_asm(asm: &[&[&str]]) -> (Vec<Vec<String>>, HashSet<usize>) {
+        let mut commands: Vec<Command> = asm.iter()
+            .map(|lines| Command::from_asm(lines.iter().map(|line| line.to_string()).collect(), "Test"))
+            .collect();
+
+        let fused = optimize(&mut commands);
+        let processed = commands.iter().map(|command| command.get_processed().unwrap().clone()).collect();
+
+        return (processed, fused);
+    }
+
+    /// Checks that the program behaves like the VM interpreter both with and without the optimizer
+    fn assert_matches_interpreter(files: &[(&str, &str)], bootstrap: bool, ram: &[(u16, i16)]) {
+        for optimize in [false, true] {
+            let mut translator = Translator::new();
+            translator.bootstrap(bootstrap).optimize(optimize);
+
+            for (name, text) in files {
+                translator.add_source(name, text);
+            }
+
+            let translation = translator.translate().unwrap();
+            let comparison = differential::compare(&translation, ram, 10_000).unwrap();
+
+            if let Some(divergence) = comparison.divergence {
+                panic!("optimize {}: {}", optimize, divergence);
+            }
+            assert!(comparison.stop_reason != StopReason::CycleLimit);
+        }
+    }
+
+    #[test]
+    fn removes_push_then_pop_of_d() {
+        let (processed, fused) = optimize_asm(&[
+            &["@5", "D=A", "@SP", "M=M+1", "A=M-1", "M=D"],
+            &["@SP", "AM=M-1", "D=M", "@R13", "M=D"]
+        ]);
+
+        assert_eq!(processed, vec![vec!["@5", "D=A"], vec!["@R13", "M=D"]]);
+        assert_eq!(fused, HashSet::from([0]));
+    }
+
+    #[test]
+    fn rewrites_increment_then_pop() {
+        let (processed, fused) = optimize_asm(&[
+            &["@SP", "M=M+1"],
+            &["@SP", "AM=M-1", "D=M"]
+        ]);
+
+        assert_eq!(processed, vec![vec!["@SP"], vec!["A=M", "D=M"]]);
+        assert_eq!(fused, HashSet::from([0]));
+    }
+
+    #[test]
+    fn removes_unused_a_load() {
+        let (processed, fused) = optimize_asm(&[
+            &["D=M", "@5"],
+            &["@7", "D=D+A"]
+        ]);
+
+        assert_eq!(processed, vec![vec!["D=M"], vec!["@7", "D=D+A"]]);
+        assert_eq!(fused, HashSet::from([0]));
+    }
+
+    #[test]
+    fn keeps_patterns_across_labels() {
+        let asm: &[&[&str]] = &[
+            &["@SP", "M=M+1", "A=M-1", "M=D"],
+            &["(LOOP)", "@SP", "AM=M-1", "D=M"],
+            &["@5"],
+            &["(END)", "@END", "0;JMP"]
+        ];
+
+        let (processed, fused) = optimize_asm(asm);
+
+        assert_eq!(processed, asm.iter().map(|lines| lines.to_vec()).collect::<Vec<_>>());
+        assert!(fused.is_empty());
+    }
+
+    #[test]
+    fn arithmetic_matches_interpreter() {
+        let program = "push constant 17\npush constant 17\neq\npush constant 892\npush constant 891\nlt\n\
+            push constant 32767\npush constant 32766\ngt\npush constant 57\npush constant 31\nadd\n\
+            push constant 53\nsub\nneg\npush constant 82\nand\npush constant 112\nor\nnot\n";
+
+        assert_matches_interpreter(&[("Arithmetic.vm", program)], false, &[(0, 256)]);
+    }
+
+    #[test]
+    fn branching_matches_interpreter() {
+        let program = "function Sys.init 2\npush constant 10\npop local 0\nlabel LOOP\npush local 0\n\
+            push local 1\nadd\npop local 1\npush local 0\npush constant 1\nsub\npop local 0\npush local 0\n\
+            if-goto LOOP\npush local 1\npush constant 55\neq\nif-goto DONE\npush constant 1\npop temp 0\n\
+            label DONE\ngoto DONE\n";
+
+        assert_matches_interpreter(&[("Sys.vm", program)], true, &[]);
+    }
+
+    #[test]
+    fn call_and_return_match_interpreter() {
+        assert_matches_interpreter(&[
+            ("Main.vm", include_str!("../tests/fixtures/FibonacciElement/Main.vm")),
+            ("Sys.vm", include_str!("../tests/fixtures/FibonacciElement/Sys.vm"))
+        ], true, &[]);
+    }
+
+    #[test]
+    fn statics_match_interpreter() {
+        assert_matches_interpreter(&[
+            ("Class1.vm", include_str!("../tests/fixtures/StaticsTest/Class1.vm")),
+            ("Class2.vm", include_str!("../tests/fixtures/StaticsTest/Class2.vm")),
+            ("Sys.vm", include_str!("../tests/fixtures/StaticsTest/Sys.vm"))
+        ], true, &[]);
+    }
+}
@@ -1,11 +1,12 @@
 use std::str::FromStr;
 
-use crate::constants;
-use crate::{Operation, operations::{OperationType, Segment, ARITHMETIC_OPERATION, BRANCHING_OPERATION, MEMORY_OPERATION, FUNCTION_OPERATION}};
+use crate::diagnostic::{Diagnostic, Span};
+use crate::error::TranslateError;
+use crate::instruction::VmInstruction;
+use crate::operations::{Operation, OperationType, Segment, ARITHMETIC_OPERATION, BRANCHING_OPERATION, MEMORY_OPERATION, FUNCTION_OPERATION};
 
+/// A single line of VM code parsed into an instruction, along with the assembly generated for it
 pub struct Command {
-    /// Command number of current for loops
-    command_count: u32,
     /// Name of the program being executed (used by static and stuff)
     program_name: String,
     /// If it has a valid command or if it is blank
@@ -14,51 +15,51 @@ pub struct Command {
     command_string: String,
     /// Original string split by whitespace
     command_tokens: Vec<String>,
-    /// Operation being performed
-    operation: Operation,
-    /// Operation type being performed
-    operation_type: OperationType,
-    /// Memory segment being operated on
-    segment: Segment,
-    /// Location index or value of the segment being written to
-    segment_i: u32,
-    /// Resulting command strings (assembly commands) after the original command is processed
+    /// Column range of each token in the original string
+    token_columns: Vec<(usize, usize)>,
+    /// Location of the command in its source file
+    span: Option<Span>,
+    /// Warnings raised while parsing the command
+    warnings: Vec<Diagnostic>,
+    /// Instruction parsed from the command
+    instruction: Option<VmInstruction>,
+    /// Resulting command strings (assembly commands) after the instruction is generated
     parsed_cmd: Vec<String>,
 
 }
 
 impl Command {
-    pub fn new(command_str: &str, command_cnt: u32, prgm_name: &str) -> Self {
+    pub fn new(command_str: &str, prgm_name: &str, span: Option<Span>) -> Result<Self, Diagnostic> {
         let mut command = Command {
-            command_count: command_cnt,
             program_name: prgm_name.to_owned(),
             is_valid: false,
             command_string: command_str.to_owned(),
             command_tokens: vec![],
-            operation: Operation::Default,
-            operation_type: OperationType::Default,
-            segment: Segment::Default,
-            segment_i: 0,
+            token_columns: vec![],
+            span,
+            warnings: Vec::new(),
+            instruction: None,
             parsed_cmd: Vec::new()
         };
 
-        command.is_valid = command.parse();
+        command.is_valid = command.parse()?;
 
-        return command;
+        return Ok(command);
     }
 
-    /// Writes in the init commands and wipes all other Command memory
-    pub fn write_init(&mut self) {
-        self.parsed_cmd.clear();
-
-        self.parsed_cmd.extend([
-            "@256".to_string(),
-            "D=A".to_string(),
-            "@SP".to_string(),
-            "M=D".to_string(),
-        ]);
-
-        self.is_valid = true;
+    /// Creates a command holding generated assembly that doesn't come from any VM code
+    pub fn from_asm(asm: Vec<String>, prgm_name: &str) -> Self {
+        return Command {
+            program_name: prgm_name.to_owned(),
+            is_valid: true,
+            command_string: String::new(),
+            command_tokens: vec![],
+            token_columns: vec![],
+            span: None,
+            warnings: Vec::new(),
+            instruction: None,
+            parsed_cmd: asm
+        };
     }
 
     pub fn get_processed(&self) -> Option<&Vec<String>> {
@@ -68,251 +69,89 @@ impl Command {
         return Some(&self.parsed_cmd);
     }
 
-    pub fn has_command(&self) -> bool {
-        return self.is_valid;
-    }
-
-    /// Adds an assembly line command to the command vector for this VM command
-    fn append_cmd(&mut self, cmd: &str) {
-        self.parsed_cmd.push(cmd.to_owned());
-    }
-
-    /// Goes to specified address in the RAM with condition (None => "JMP")
-    /// 
-    /// Ex:
-    ///     goto_addr("TEST_LABEL", "JLT") 
-    ///      |
-    ///      |
-    ///      V
-    ///     @TEST_LABEL
-    ///     0; JLT
-    fn branch_addr(&mut self, addr: &str, jmp_cmd: Option<&str>) {
-        self.set_addr(addr);
-
-        let jmp;
-
-        match jmp_cmd {
-            Some(jmp_tst) => {
-                jmp = jmp_tst;
-            },
-            None => {
-                jmp = "JMP";
-            }
-        }
-
-        self.append_cmd(&format!("D; {}", jmp));
+    /// Stores the assembly generated for the instruction, headed by the original command as a comment
+    pub fn set_processed(&mut self, asm: Vec<String>) {
+        self.parsed_cmd.clear();
+        self.parsed_cmd.push(format!("// {}", self.command_string));
+        self.parsed_cmd.extend(asm);
     }
 
-    /// Sets address to the given string
-    fn set_addr(&mut self, addr: &str) {
-        self.append_cmd(&format!("@{}", addr));
+    /// Replaces the generated assembly as is, used by passes over the whole program
+    pub(crate) fn replace_processed(&mut self, asm: Vec<String>) {
+        self.parsed_cmd = asm;
     }
 
-    /// Appends the label to the command
-    /// 
-    /// Ex:
-    ///     label
-    ///         -> "({label})"
-    fn append_label(&mut self, label: &str) {
-        self.append_cmd(format!("({})", label).as_str());
+    pub fn has_command(&self) -> bool {
+        return self.is_valid;
     }
 
-    /// Jumps to the given label such in the goto command
-    fn jump_to_label(&mut self, label: &str) {
-        self.set_addr(label);
-        self.append_cmd("0; JMP");
+    /// Instruction parsed from the command, None if the line holds no command
+    pub fn instruction(&self) -> Option<&VmInstruction> {
+        return self.instruction.as_ref();
     }
 
-    /// Pushes the label's address onto the stack
-    fn push_label_addr(&mut self, label: &str) {
-        self.set_addr(label);
-        self.append_cmd("D=A");
-        self.push_d();
+    /// Name of the module the command belongs to
+    pub fn program_name(&self) -> &str {
+        return &self.program_name;
     }
 
-    /// Pushes the address of the passed in segment to the stack
-    fn push_segment_label(&mut self, segment: Segment) {
-        self.set_addr(segment.to_string().as_str());
-        self.append_cmd("D=M");
-        self.push_d();
+    /// Location of the command in its source file
+    pub fn span(&self) -> Option<&Span> {
+        return self.span.as_ref();
     }
 
-    /// Calls a function
-    /// 
-    /// Saves the current stack and return address
-    fn call_func(&mut self, function_name: &str, nargs: u32) {
-        // Push return address
-        let return_addr = format!("{}_{}", function_name, self.command_count);
-        self.push_label_addr(return_addr.as_str());
-
-        // Push LCL
-        self.push_segment_label(Segment::Local);
-
-        // Push ARG
-        self.push_segment_label(Segment::Argument);
-
-        // Push THIS
-        self.push_segment_label(Segment::This);
-
-        // Push THAT
-        self.push_segment_label(Segment::That);
-
-        // ARG = SP - 5 - nArgs
-        self.set_d(5);
-        self.set_addr(nargs.to_string().as_str());
-        self.append_cmd("D=D+A");
-        self.set_addr("SP");
-        self.append_cmd("D=M-D");
-        self.set_addr(Segment::Argument.to_string().as_str());
-        self.append_cmd("M=D");
-
-        // LCL = SP
-        self.set_addr("SP");
-        self.append_cmd("D=M");
-        self.set_addr(Segment::Local.to_string().as_str());
-        self.append_cmd("M=D");
-
-        // Goto functionName
-        self.jump_to_label(function_name);
-
-        // (returnAddress)
-        self.append_label(return_addr.as_str());
-
+    /// Takes the warnings raised while parsing this command
+    pub fn take_warnings(&mut self) -> Vec<Diagnostic> {
+        return std::mem::take(&mut self.warnings);
     }
 
-    /// Creates a function
-    /// 
-    /// Builds the new stack frame
-    fn function_func(&mut self, function_name: &str, nlocal: u32) {
-        // (functionName)
-        self.append_label(function_name);
-
-        // push 0 * nlocal
-        for _i in 0..nlocal {
-            self.set_d(0);
-            self.push_d();
-        }
-
-    }
-
-    /// Sets the address of the given segment to the value at new_addr + offset
-    fn set_segment(&mut self, segment: Segment, new_addr: &str, offset: i32) {
-        self.set_d(offset.abs().try_into().unwrap());
+    /// Splits the command on whitespace, ignoring comments, and records the columns of each token
+    fn tokenize(&mut self) {
+        let code = match self.command_string.find("//") {
+            Some(comment_index) => &self.command_string[0..comment_index],
+            None                => self.command_string.as_str()
+        };
 
-        self.set_addr(new_addr);
+        let mut token = String::new();
+        let mut start = 0;
 
-        if offset < 0 {
-            self.append_cmd("A=M-D");
-        }
-        else {
-            self.append_cmd("A=M+D");
+        // Trailing space makes sure the last token gets pushed
+        for (i, c) in code.chars().chain([' ']).enumerate() {
+            if c.is_whitespace() {
+                if !token.is_empty() {
+                    self.command_tokens.push(std::mem::take(&mut token));
+                    self.token_columns.push((start + 1, i + 1));
+                }
+            }
+            else {
+                if token.is_empty() {
+                    start = i;
+                }
+                token.push(c);
+            }
         }
-
-        // We now save this value into the segment
-        self.append_cmd("D=M");
-
-        self.set_addr(segment.to_string().as_str());
-        self.append_cmd("M=D");
-
     }
 
-    /// Returns the function back to previous address
+    /// Gets the span of the token at index i
     /// 
-    /// Cuts down current stack frame and jumps to return address on the stack
-    fn return_func(&mut self) {
-        // endFrame = LCL
-        self.set_addr(Segment::Local.to_string().as_str());
-        self.append_cmd("D=M");
-        self.set_addr("R13"); // Save LCL as endFrame in R13
-        self.append_cmd("M=D");
-
-        // retAddr = *(endFrame - 5)
-        self.set_d(5);
-        self.set_addr("R13");
-        self.append_cmd("A=M-D");
-        self.append_cmd("D=M");
-        self.set_addr("R14"); // Save return address in R14
-        self.append_cmd("M=D");
-
-        // *ARG = pop()
-        self.pop_d();
-        self.set_addr(Segment::Argument.to_string().as_str());
-        self.append_cmd("A=M");
-        self.append_cmd("M=D");
-
-        // SP = ARG + 1
-        self.set_addr(Segment::Argument.to_string().as_str());
-        self.append_cmd("D=M");
-        self.set_addr("SP");
-        self.append_cmd("M=D+1");
-
-        // THAT = *(endFame - 1)
-        self.set_segment(Segment::That, "R13", -1);
-
-        // THIS = *(endFame - 2)
-        self.set_segment(Segment::This, "R13", -2);
-
-        // ARG = *(endFame - 3)
-        self.set_segment(Segment::Argument, "R13", -3);
-        
-        // LCL = *(endFame - 4)
-        self.set_segment(Segment::Local, "R13", -4);
-
-        // goto retAddr
-        self.set_addr("R14");
-        self.append_cmd("A=M");
-        self.append_cmd("0;JMP");
-
-    }
-
-    /// Set d register to value i
-    fn set_d(&mut self, i: u32) {
-        self.set_addr(i.to_string().as_str());
-        self.append_cmd("D=A");
-    }
-
-    /// Push whatever is in d onto the stack
-    fn push_d(&mut self) {
-        self.append_cmd("@SP");
-        self.append_cmd("M=M+1");
-        self.append_cmd("A=M-1");
-        self.append_cmd("M=D");
-    }
-
-    /// Pop whatever is on the stack to d
-    fn pop_d(&mut self) {
-        self.append_cmd("@SP");
-        self.append_cmd("AM=M-1");
-        self.append_cmd("D=M");
-    }
-
-    /// Save SP1 in current
-    fn get_sp1(&mut self) {
-        self.append_cmd("@SP");     // Go to stack pointer
-        self.append_cmd("AM=M-1");  // Get pointer at SP - 1 and goto address
-    }
-
-    /// Save SP1 in D and locate at SP2
-    fn get_sp2(&mut self) {
-        self.append_cmd("D=M");     // Save the value at the address in D reg
-        self.append_cmd("@SP");     // Go back to the stack pointer
-        // ISSUE: This may cause issues if M is saved after A
-        self.append_cmd("AM=M-1");  // Go back to SP - 1 (second val) and jump to value
-        // Next line will be something like M=M+D depending on operation
-    }
+    /// Missing tokens point just past the end of the command
+    pub fn token_span(&self, i: usize) -> Option<Span> {
+        let span = self.span.as_ref()?;
+
+        let (start, end) = match self.token_columns.get(i) {
+            Some(columns)   => *columns,
+            None            => {
+                let end = self.token_columns.last().map_or(span.start_col, |columns| columns.1);
+                (end, end + 1)
+            }
+        };
 
-    /// Increment the stack pointer
-    fn inc_sp(&mut self) {
-        // Increment SP
-        self.append_cmd("@SP");
-        self.append_cmd("M=M+1");
+        return Some(span.with_columns(start, end));
     }
 
-    /// Decrement the stack pointer
-    fn dec_sp(&mut self) {
-        // Decrement SP
-        self.append_cmd("@SP");
-        self.append_cmd("M=M-1");
+    /// Creates an error diagnostic pointing at the token at index i
+    fn error_at(&self, i: usize, error: TranslateError) -> Diagnostic {
+        return Diagnostic::error(self.token_span(i), error);
     }
 
     /// Cleans the command of whitespace and comments
@@ -321,364 +160,152 @@ impl Command {
         self.command_string = self.command_string.trim().to_string();
 
         // Remove comments
-        let comment_index = self.command_string.find("//");
-        if comment_index.is_some() {
-            self.command_string = self.command_string[0..comment_index.unwrap()].to_string();
+        if let Some(comment_index) = self.command_string.find("//") {
+            self.command_string = self.command_string[0..comment_index].trim_end().to_string();
         }
 
     }
 
-    /// Run this function after loading operating value into D and decider as a param
-    ///
-    /// Example
-    ///     jump_ins = "JEQ"
-    /// 
-    ///     Now this function will write the behavior that if D is EQ 0 it will
-    ///     load true val on the stack, otherwise false val
-    fn load_bool_jumps(&mut self, jump_ins: &str) {
-        let true_label = "RESULT_TRUE";
-        let false_label = "RESULT_FALSE";
-        let set_val_label = "RESULT_SET";
-
-        self.append_cmd(&format!("@{}_{}", true_label, self.command_count)); // Set the true label
-        self.append_cmd(&format!("D;{}", jump_ins)); // Jump if D is 0
-        self.append_cmd(&format!("@{}_{}", false_label, self.command_count)); // Set false label
-        self.append_cmd("0;JMP"); // Jump regardless
-        // Now setup the labels
-        // True label
-        self.append_cmd(&format!("({}_{})", true_label, self.command_count));
-        self.append_cmd(&format!("D={}", constants::TRUE_VALUE)); // Set the true value in D
-        self.append_cmd(&format!("@{}_{}", set_val_label, self.command_count)); // Load in set label
-        self.append_cmd("0;JMP"); // Jump to that label
-
-        // False label
-        self.append_cmd(&format!("({}_{})", false_label, self.command_count));
-        self.append_cmd(&format!("D={}", constants::FALSE_VALUE)); // Set the false value in D
-        // Let it fall through into the result label
-
-        // Set value label
-        self.append_cmd(&format!("({}_{})", set_val_label, self.command_count));
-        self.append_cmd("@SP");
-        self.append_cmd("A=M"); // Go to SP
-        self.append_cmd("M=D"); // Set *SP to the result val
-        // Now increment sp
-        self.inc_sp();
-
+    /// Gets the operand at index i of the command tokens
+    fn operand(&self, i: usize, name: &'static str) -> Result<&str, Diagnostic> {
+        match self.command_tokens.get(i) {
+            Some(token) => Ok(token),
+            None        => Err(self.error_at(i, TranslateError::MissingOperand {
+                command: self.command_tokens[0].clone(),
+                operand: name
+            }))
+        }
+    }
 
+    /// Gets the operand at index i of the command tokens as a non-negative number
+    fn index_operand(&self, i: usize, name: &'static str) -> Result<u32, Diagnostic> {
+        let token = self.operand(i, name)?;
+        return token.parse().map_err(|_| self.error_at(i, TranslateError::InvalidIndex(token.to_owned())));
     }
 
     /// Determines the correct parse call for the given operation type
-    fn parse(&mut self) -> bool {
-        // Check if it's a comment
-        if self.command_string.find("//") == Some(0) || self.command_string == "" {
-            return false;
-        }
+    /// 
+    /// Returns false if the line holds no command (blank or comment)
+    fn parse(&mut self) -> Result<bool, Diagnostic> {
+        // Tokenize commands
+        self.tokenize();
 
         self.clean_command();
 
-        // Tokenize commands
-        self.command_tokens = self.command_string.split_whitespace().map(str::to_string).collect();
-
-        // Clear and append header of operation (original content)
-        self.parsed_cmd.clear();
-        self.append_cmd(&format!("// {}", self.command_string));
+        // Check if it's a comment or blank line
+        if self.command_tokens.is_empty() {
+            return Ok(false);
+        }
 
         let operation_str = &self.command_tokens[0];
-        self.operation = Operation::from_str(operation_str).unwrap();
+        let operation = Operation::from_str(operation_str).map_err(|e| self.error_at(0, e))?;
 
         // Figure out what type of operation
-        if ARITHMETIC_OPERATION.contains(self.operation) {
-            self.operation_type = OperationType::Arithmetic;
+        let operation_type = if ARITHMETIC_OPERATION.contains(operation) {
+            OperationType::Arithmetic
         }
-        else if BRANCHING_OPERATION.contains(self.operation) {
-            self.operation_type = OperationType::Branching;
+        else if BRANCHING_OPERATION.contains(operation) {
+            OperationType::Branching
         }
-        else if MEMORY_OPERATION.contains(self.operation) {
-            self.operation_type = OperationType::Memory;
+        else if MEMORY_OPERATION.contains(operation) {
+            OperationType::Memory
         }
-        else if FUNCTION_OPERATION.contains(self.operation) {
-            self.operation_type = OperationType::Function;
+        else if FUNCTION_OPERATION.contains(operation) {
+            OperationType::Function
         }
         else {
-            self.operation_type = OperationType::Default;
-        }
+            OperationType::Default
+        };
 
         // Now we will match on operation_type
-        match self.operation_type {
-            OperationType::Arithmetic => self.parse_arithmetic(),
-            OperationType::Branching => self.parse_branching(),
-            OperationType::Memory => self.parse_memory(),
-            OperationType::Function => self.parse_function(),
-            OperationType::Default => {}
+        self.instruction = match operation_type {
+            OperationType::Arithmetic => Some(VmInstruction::Arithmetic(operation)),
+            OperationType::Branching => Some(self.parse_branching(operation)?),
+            OperationType::Memory => Some(self.parse_memory(operation)?),
+            OperationType::Function => Some(self.parse_function(operation)?),
+            OperationType::Default => None
 
-        }
-        
-        return true;
-
-    }
+        };
 
-    /// Parse arithmetic command into its hack commands
-    fn parse_arithmetic(&mut self) {
-        // Check if it is not or neg as both of those only take one argument
-        if self.operation == Operation::Neg {
-            self.get_sp1();
-            self.append_cmd("M=-M"); // Make M negative and save
-            self.inc_sp();
-        }
-        else if self.operation == Operation::Not {
-            self.get_sp1();
-            self.append_cmd("M=!M"); // Negate M
-            self.inc_sp();
-        }
+        // Warn about anything after the last operand
+        let operand_count = match operation {
+            Operation::Label | Operation::Goto | Operation::IfGoto  => 2,
+            Operation::Push | Operation::Pop                        => 3,
+            Operation::Function | Operation::Call                   => 3,
+            _                                                       => 1
+        };
 
-        // Match on operation
-        match self.operation {
-            Operation::Add  => {
-                self.get_sp1();
-                self.get_sp2();
-                self.append_cmd("M=M+D"); // Insert the new value at SP
-                self.inc_sp();
-            },
-            Operation::Sub  => {
-                self.get_sp1();
-                self.get_sp2();
-                self.append_cmd("M=M-D");
-                self.inc_sp();
-            },
-            Operation::Eq   => {
-                self.get_sp1();
-                self.get_sp2();
-                self.append_cmd("D=M-D"); // If equal this value is 0
-                self.load_bool_jumps("JEQ"); // We want true if eq
-                // self.inc_sp();
-            },
-            Operation::Gt   => {
-                self.get_sp1();
-                self.get_sp2();
-                self.append_cmd("D=M-D"); // We want x - y which is M - D
-                self.load_bool_jumps("JGT"); // We want true if gt
-                // self.inc_sp();
-            },
-            Operation::Lt   => {
-                self.get_sp1();
-                self.get_sp2();
-                self.append_cmd("D=M-D");
-                self.load_bool_jumps("JLT"); // We want true if lt
-                // self.inc_sp();
-            },
-            Operation::And  => {
-                self.get_sp1();
-                self.get_sp2();
-                self.append_cmd("D=M&D");
-                self.push_d();
-                // self.inc_sp();
-            },
-            Operation::Or   => {
-                self.get_sp1();
-                self.get_sp2();
-                self.append_cmd("D=M|D");
-                self.push_d();
-                // self.inc_sp();
-            }
-            _               => {}
+        if let Some(token) = self.command_tokens.get(operand_count) {
+            self.warnings.push(Diagnostic::warning(
+                self.token_span(operand_count),
+                TranslateError::UnexpectedToken(token.clone())
+            ));
         }
+        
+        return Ok(self.instruction.is_some());
 
     }
 
-    /// Parse branching command into its hack commands
-    fn parse_branching(&mut self) {
-        let label = self.command_tokens[1].to_string();
-        match self.operation {
-            Operation::Label    => {
-                self.append_cmd(&format!("({})", label));
-            },
-            Operation::Goto     => {
-                self.branch_addr(&label, None);
-            },
-            Operation::IfGoto   => {
-                self.pop_d();
-                
-                self.branch_addr(&label, Some("JNE"));
-
-            },
-            _                   => {}
-        }
-    }
-
-    // Executes a memory command
-    fn exec_memory(&mut self, op: Operation, segment: Segment, segment_i: u32) {
-        // Save memory location as a string
-        let mut memory_addr = Segment::to_string(&segment);
-
-        // Parse local, argument, this, that
-        if segment == Segment::Local || segment == Segment::Argument
-            || segment == Segment::This || segment == Segment::That {
-
-            if op == Operation::Push {
-                // Get the offset in the d register
-                self.set_d(segment_i.try_into().unwrap());
-                // Get to the new memory address and add in the offset
-                self.append_cmd(&format!("@{}", memory_addr));
-                self.append_cmd("A=D+M"); // Go to the address
-
-                // Get value at ram in d reg
-                self.append_cmd("D=M");
-                // Now push d
-                self.push_d();
-            }
-            else if op == Operation::Pop {
-                // Get the offset in d
-                self.set_d(segment_i.try_into().unwrap());
-                
-                // Now go to the base and get address (base + i) in d
-                self.append_cmd(&format!("@{}", memory_addr));
-                self.append_cmd("D=M+D");
-
-                // Now save d in R13
-                self.append_cmd("@R13");
-                self.append_cmd("M=D");
-
-                // Get SP value into d
-                self.pop_d();
-
-                // Go to R13 and follow the pointer
-                self.append_cmd("@R13");
-                self.append_cmd("A=M");
+    /// Parse branching command into its instruction
+    fn parse_branching(&self, operation: Operation) -> Result<VmInstruction, Diagnostic> {
+        let label = self.operand(1, "label")?.to_string();
+        let instruction = match operation {
+            Operation::Label    => VmInstruction::Label(label),
+            Operation::Goto     => VmInstruction::Goto(label),
+            _                   => VmInstruction::IfGoto(label)
+        };
 
-                // Now Save D into M
-                self.append_cmd("M=D");
+        instruction.validate().map_err(|e| self.error_at(1, e))?;
 
-            }
-            else {
-                panic!("Impossible error");
-            }
-        }
-        else if segment == Segment::Static {
-            if op == Operation::Push {
-                // Go to memory location
-                self.append_cmd(&format!("@{}.{}", self.program_name, segment_i));
-                // Get the value in d
-                self.append_cmd("D=M");
-                // Push d to the stack
-                self.push_d();
-            }
-            else if op == Operation::Pop {
-                // Pop d
-                self.pop_d();
-                // Go to memory location
-                self.append_cmd(&format!("@{}.{}", self.program_name, segment_i));
-                // Set M to d
-                self.append_cmd("M=D");
-            }
-            else {
-                panic!("Impossible error");
-            }
-        }
-        else if segment == Segment::Temp {
-            let addr = constants::TEMP_START + segment_i;
-
-            if op == Operation::Push {
-                // Go to address
-                self.append_cmd(&format!("@{}", addr));
-                // Set d to m
-                self.append_cmd("D=M");
-                // Push d
-                self.push_d();        
-            }
-            else if op == Operation::Pop {
-                // Pop d
-                self.pop_d();
-                // Go to address
-                self.append_cmd(&format!("@{}", addr));
-                // Set m to d
-                self.append_cmd("M=D");
-            }
-            else {
-                panic!("Impossible error");
-            }
-        }
-        else if segment == Segment::Pointer {
-            // Get corresponding segment
-            memory_addr = if segment_i == 0 {"THIS".to_owned()} else {"THAT".to_owned()};
-
-            if op == Operation::Push {
-                // Go to either this or that
-                self.append_cmd(&format!("@{}", memory_addr));
-                // Store address on stack
-                // ISSUE ? This might need to be M instead of D
-                self.append_cmd("D=M");
-                self.push_d();
-            }
-            else if op == Operation::Pop {
-                // Pop D
-                self.pop_d();
-                // Go to this or that and store value into it from stack
-                self.append_cmd(&format!("@{}", memory_addr));
-                self.append_cmd("M=D");
-            }
-            else {
-                panic!("Impossible error");
-            }
-        }
-        else if segment == Segment::Constant {
-            if op == Operation::Push {
-                // Get the constant value
-                self.set_d(segment_i.try_into().unwrap());
-                self.push_d();
-            }
-            else if op == Operation::Pop {
-                panic!("Impossible to pop a constant");
-            }
-            else {
-                panic!("Impossible error");
-            }
-        }
+        return Ok(instruction);
     }
 
-    /// Parse memory command into its hack commands
-    fn parse_memory(&mut self) {
+    /// Parse memory command into its instruction
+    fn parse_memory(&self, operation: Operation) -> Result<VmInstruction, Diagnostic> {
         // Assign segment
-        self.segment = Segment::from_str(&self.command_tokens[1]).unwrap();
+        let segment = Segment::from_str(self.operand(1, "segment")?).map_err(|e| self.error_at(1, e))?;
 
         // Assign segment index
-        self.segment_i = self.command_tokens[2].parse().unwrap();
+        let index = self.index_operand(2, "index")?;
 
-        self.exec_memory(self.operation, self.segment, self.segment_i);
-        
+        let instruction = if operation == Operation::Push {
+            VmInstruction::Push { segment, index }
+        }
+        else {
+            VmInstruction::Pop { segment, index }
+        };
+
+        // Point at the index if it's out of range, otherwise at the segment
+        instruction.validate().map_err(|e| match e {
+            TranslateError::IndexOutOfRange { .. }  => self.error_at(2, e),
+            _                                       => self.error_at(1, e)
+        })?;
+
+        return Ok(instruction);
     }
 
-    /// Parse function command into its hack commands
-    fn parse_function(&mut self) {
-        let function_name_op: Option<String>;
-        let args_op: Option<u32>;
+    /// Parse function command into its instruction
+    fn parse_function(&self, operation: Operation) -> Result<VmInstruction, Diagnostic> {
+        if operation == Operation::Return {
+            return Ok(VmInstruction::Return);
+        }
+
+        let name = self.operand(1, "function name")?.to_string();
+        let count = self.index_operand(2, "count")?;
 
-        if self.operation == Operation::Call || self.operation == Operation::Function {
-            function_name_op = Some(self.command_tokens[1].to_string());
-            args_op = Some(self.command_tokens[2].parse().unwrap());
+        let instruction = if operation == Operation::Call {
+            VmInstruction::Call { name, nargs: count }
         }
         else {
-            function_name_op = None;
-            args_op = None;
-        }
-        
+            VmInstruction::Function { name, nlocals: count }
+        };
 
-        match self.operation {
-            Operation::Call => {
-                let function_name = function_name_op.unwrap();
-                let nargs = args_op.unwrap();
-
-                self.call_func(&function_name, nargs);
-            },
-            Operation::Return => {
-                self.return_func();
-            },
-            Operation::Function => {
-                let function_name = function_name_op.unwrap();
-                let nlocal = args_op.unwrap();
-
-                self.function_func(&function_name, nlocal);
-            },
-            _ => {}
-        }
+        // Point at the count if it's out of range, otherwise at the name
+        instruction.validate().map_err(|e| match e {
+            TranslateError::CountOutOfRange { .. }  => self.error_at(2, e),
+            _                                       => self.error_at(1, e)
+        })?;
+
+        return Ok(instruction);
     }
-}
\ No newline at end of file
+}
@@ -1,5 +1,6 @@
+#[allow(clippy::module_inception)]
 mod parser;
 mod command;
 
-pub use parser::Parser;
+pub use parser::{find_vm_files, read_input, write_output, Parser, ProjectOptions};
 pub use command::Command;
\ No newline at end of file
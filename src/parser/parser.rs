@@ -1,126 +1,352 @@
-use crate::parser::Command;
+use crate::assembler;
+use crate::constants;
+use crate::diagnostic::Diagnostic;
+use crate::error::TranslateError;
+use crate::manifest::Manifest;
+use crate::source_map;
+use crate::translator::{TranslateOptions, Translation, Translator};
 
-use std::fs::{File, metadata, read_dir};
-use std::path::Path;
-use std::io::{prelude::*, BufReader};
+use std::collections::HashSet;
+use std::fs::{File, canonicalize, metadata, read_dir, read_to_string};
+use std::io::{self, prelude::*};
+use std::path::{Path, PathBuf};
 
-use substring::Substring;
-
-#[derive(PartialEq, Eq)]
-enum FileType {
-    None,
-    File,
-    Directory
+/// How the vm files of a project are found and ordered
+#[derive(Debug, Clone, Default)]
+pub struct ProjectOptions {
+    /// Look for vm files in subdirectories of input directories too
+    pub recursive: bool,
+    /// File translated before all the others, by path, name or module (ex. Sys.vm or Sys)
+    pub first: Option<String>,
+    /// Module name of VM code read from stdin (-), Main if not given
+    pub name: Option<String>
 }
 
+/// Loads vm files and directories of vm files from disk and translates them into one program
 pub struct Parser {
-    vm_path: String,
-    file_type: FileType,
-    cmd_cnt: u32,
-    command_list: Vec<Command>
+    vm_paths: Vec<String>,
+    project: ProjectOptions,
+    /// Every vm file in the order it was translated
+    files: Vec<String>,
+    translator: Translator,
+    translation: Option<Translation>,
+    /// Errors and warnings raised while processing the project
+    diagnostics: Vec<Diagnostic>
 }
 
 impl Parser {
-    pub fn new(fname: &str) -> Self {
+    /// Processes every file in the project
+    /// 
+    /// Unless set in the options, the bootstrap code is only written for directories
+    /// 
+    /// Fails with every diagnostic found, sorted by file and line, if any of them is an error
+    pub fn new(fname: &str, options: TranslateOptions) -> Result<Self, Vec<Diagnostic>> {
+        return Parser::with_paths(&[fname], ProjectOptions::default(), options);
+    }
+
+    /// Processes every file and directory given, linking them into a single program
+    /// 
+    /// Unless set in the options, the bootstrap code is only written for directories or more than
+    /// one path
+    /// 
+    /// Files are translated in the order given, with the files of a directory sorted by path. A
+    /// directory's vmproject.toml can name its source directories and list files to translate first,
+    /// and the project options can move one file to the very front
+    /// 
+    /// Fails with every diagnostic found, sorted by file and line, if any of them is an error
+    pub fn with_paths<S: AsRef<str>>(paths: &[S], project: ProjectOptions, options: TranslateOptions) -> Result<Self, Vec<Diagnostic>> {
         let mut parser = Parser {
-            vm_path: fname.to_owned(),
-            file_type: FileType::None,
-            cmd_cnt: 0,
-            command_list: Vec::new()
+            vm_paths: paths.iter().map(|path| path.as_ref().to_owned()).collect(),
+            project,
+            files: Vec::new(),
+            translator: Translator::with_options(options),
+            translation: None,
+            diagnostics: Vec::new()
         };
 
         parser.process_project();
 
-        return parser;
+        match parser.translator.translate() {
+            Ok(mut translation) => {
+                parser.diagnostics.extend(translation.take_diagnostics());
+                parser.translation = Some(translation);
+            },
+            Err(diagnostics) => parser.diagnostics.extend(diagnostics)
+        }
+
+        parser.diagnostics.sort_by(|a, b| a.span.cmp(&b.span));
+
+        if parser.diagnostics.iter().any(Diagnostic::is_error) {
+            return Err(parser.diagnostics);
+        }
+
+        return Ok(parser);
+    }
+
+    /// Warnings raised while processing the project
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        return &self.diagnostics;
     }
 
-    /// Writes the initializer code into the output list
-    fn write_init(&mut self) {
-        let mut command = Command::new("", self.cmd_cnt, "");
-        self.cmd_cnt += 1;
-        command.write_init();
-        self.command_list.push(command);
-        let sysinit = Command::new("call Sys.init 0", self.cmd_cnt, "");
-        self.cmd_cnt += 1;
-        self.command_list.push(sysinit);
+    /// Takes the warnings raised while processing the project
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        return std::mem::take(&mut self.diagnostics);
     }
 
-    /// Processes a vm file and appends its commands to the command_list
-    fn process_file(&mut self, file_path: &str) {
-        // Get base file name
-        let path = Path::new(file_path);
-        let full_name = path.file_name().unwrap().to_os_string().into_string().unwrap();
+    /// Every vm file in the order it was translated
+    pub fn files(&self) -> &[String] {
+        return &self.files;
+    }
+
+    /// Translation of the whole project
+    pub fn translation(&self) -> &Translation {
+        return self.translation.as_ref().unwrap();
+    }
 
-        let file_name = full_name.substring(0, full_name.find(".").unwrap());
+    /// Reads a vm file, or stdin if the path is -, and adds it to the translator
+    fn process_file(&mut self, file_path: &str) -> Result<(), Diagnostic> {
+        let vm_code = read_input(file_path)?;
 
-        let vm_code = File::open(file_path).unwrap();
-        let bf = BufReader::new(vm_code);
+        if file_path != constants::STDIO_PATH {
+            self.translator.add_source(file_path, &vm_code);
+            return Ok(());
+        }
 
-        for line  in bf.lines() {
-            let command = Command::new(&line.unwrap(), self.cmd_cnt, file_name);
+        // Static variables are named after the module, which stdin has no file name to give
+        let module = self.project.name.as_deref().unwrap_or(constants::DEFAULT_MODULE);
 
-            if command.has_command() {
-                self.command_list.push(command);
-                self.cmd_cnt += 1;
-            }
+        if !assembler::is_symbol(module) {
+            return Err(TranslateError::InvalidOption(format!("'{}' is not a valid module name", module)).into());
         }
+
+        self.translator.add_module_source(constants::STDIN_NAME, module, &vm_code);
+
+        return Ok(());
     }
 
-    /// Takes in a directory and loads in and processes each vm file within
-    fn process_directory(&mut self, dir_path: &str) {
-        let filenames = read_dir(dir_path).unwrap();
-        
-        // Loop through each file in the directory and list vm files
-        for filename in filenames {
-            let file = filename.unwrap().path().as_os_str().to_str().unwrap().to_owned();
+    /// Lists the vm files in a directory sorted by path, after any the directory's manifest orders
+    /// 
+    /// When the directory has a manifest listing source directories, their files are used instead
+    fn process_directory(&mut self, dir_path: &str) -> Result<Vec<String>, Diagnostic> {
+        let manifest = match Manifest::find(dir_path)? {
+            Some(manifest)  => manifest,
+            None            => {
+                let mut files = Vec::new();
+                find_vm_files(dir_path, self.project.recursive, &mut files, &mut self.diagnostics)?;
+                return Ok(files);
+            }
+        };
+
+        let recursive = self.project.recursive || manifest.recursive;
+        let mut files = Vec::new();
+
+        if manifest.sources.is_empty() {
+            find_vm_files(dir_path, recursive, &mut files, &mut self.diagnostics)?;
+        }
+
+        for source in manifest.sources.iter() {
+            let source_path = manifest.resolve(source.get_ref()).to_string_lossy().into_owned();
 
-            if file.contains(".vm") {
-                self.process_file(file.as_str());
+            if let Err(diagnostic) = find_vm_files(&source_path, recursive, &mut files, &mut self.diagnostics) {
+                self.diagnostics.push(diagnostic);
             }
+        }
+
+        let mut ordered = Vec::new();
+
+        for entry in manifest.order.iter() {
+            let entry_path = manifest.resolve(entry.get_ref());
+
+            match files.iter().position(|file| Path::new(file) == entry_path) {
+                Some(i) => ordered.push(files.remove(i)),
+                None    => self.diagnostics.push(Diagnostic::error(
+                    Some(manifest.span(entry)),
+                    TranslateError::UnknownFile(entry.get_ref().clone())
+                ))
+            }
+        }
+
+        ordered.append(&mut files);
+
+        return Ok(ordered);
+    }
 
+    /// Moves the file chosen to go first to the front of the list
+    fn move_first(&mut self, files: &mut Vec<String>, first: &str) {
+        let matches = |file: &String| {
+            let path = Path::new(file);
+            path == Path::new(first)
+                || path.file_name().is_some_and(|name| name == first)
+                || path.file_stem().is_some_and(|stem| stem == first)
+        };
+
+        match files.iter().position(matches) {
+            Some(i) => {
+                let file = files.remove(i);
+                files.insert(0, file);
+            },
+            None    => self.diagnostics.push(TranslateError::UnknownFile(first.to_owned()).into())
         }
+    }
+
+    /// Drops every file after the first that points at the same file on disk, with a warning
+    fn remove_duplicates(&mut self, files: &mut Vec<String>) {
+        let mut seen = HashSet::new();
+
+        files.retain(|file| {
+            // The same file can be reached through different paths (ex. Sys.vm and ./Sys.vm)
+            let path = canonicalize(file).unwrap_or_else(|_| PathBuf::from(file));
 
+            if seen.insert(path) {
+                return true;
+            }
+
+            self.diagnostics.push(Diagnostic::warning(None, TranslateError::DuplicateInput(file.clone())));
+            return false;
+        });
     }
 
     fn process_project(&mut self) {
-        // Check if this is a file or directory
-        let md = metadata(self.vm_path.as_str()).unwrap();
-        if md.is_dir() {
-            self.file_type = FileType::Directory;
+        let mut has_directory = false;
+        let mut files = Vec::new();
+
+        for vm_path in self.vm_paths.clone() {
+            if vm_path == constants::STDIO_PATH {
+                files.push(vm_path);
+                continue;
+            }
+
+            // Check if this is a file or directory
+            let md = match metadata(vm_path.as_str()) {
+                Ok(md) => md,
+                Err(e) => {
+                    self.diagnostics.push(TranslateError::io(&vm_path, e).into());
+                    continue;
+                }
+            };
+
+            if md.is_dir() {
+                has_directory = true;
+
+                match self.process_directory(vm_path.as_str()) {
+                    Ok(dir_files) if dir_files.is_empty()   => {
+                        self.diagnostics.push(TranslateError::NoVmFiles(vm_path.clone()).into());
+                    },
+                    Ok(mut dir_files)                       => files.append(&mut dir_files),
+                    Err(diagnostic)                         => self.diagnostics.push(diagnostic)
+                }
+            }
+            else {
+                files.push(vm_path);
+            }
         }
-        else {
-            self.file_type = FileType::File;
+
+        self.remove_duplicates(&mut files);
+
+        if let Some(first) = self.project.first.clone() {
+            self.move_first(&mut files, &first);
         }
 
-        // Process for file
-        match self.file_type {
-            FileType::File => {
-                self.process_file(self.vm_path.clone().as_str())
-            },
-            FileType::Directory => {
-                self.write_init();
-                self.process_directory(self.vm_path.clone().as_str())
-            },
-            _ => panic!("Input path ({}) is not a valid path", self.vm_path)
+        // A file that can't be read shouldn't hide errors in the others
+        for file in files.iter() {
+            if let Err(diagnostic) = self.process_file(file) {
+                self.diagnostics.push(diagnostic);
+            }
+        }
+
+        self.files = files;
+
+        // A directory or several files make up a whole program
+        if self.translator.options().bootstrap.is_none() && (has_directory || self.vm_paths.len() > 1) {
+            self.translator.bootstrap(true);
         }
+    }
 
+    /// Writes the generated assembly, to stdout if the path is -
+    pub fn output(&mut self, output_path: &str) -> Result<(), TranslateError> {
+        return write_output(output_path, &self.translation().to_asm_string());
     }
 
-    pub fn output(&mut self, output_path: &str) {
-        // Open file for outputting
-        let mut asm_code = File::create(output_path).expect("Failed to open file");
+    /// Writes the listing of every command and the ROM addresses of its instructions
+    pub fn output_listing(&self, output_path: &str) -> Result<(), TranslateError> {
+        return write_output(output_path, &self.translation().listing());
+    }
 
-        // Loop through processed commands
-        for cmd in self.command_list.iter() {
-            // Loop through each string in cmd
-            for asm_cmd in cmd.get_processed().unwrap().iter() {
-                asm_code.write_all(format!("{}\n", asm_cmd).as_bytes()).expect("Failed to write");
-            }
+    /// Writes the JSON source map from the generated assembly back to the VM code
+    pub fn output_source_map(&self, output_path: &str) -> Result<(), TranslateError> {
+        return write_output(output_path, &source_map::source_map(self.translation()));
+    }
+
+    /// Assembles the translation and writes it out as .hack machine code
+    /// 
+    /// Errors in the generated assembly are reported against the .asm file it would have been written to
+    pub fn output_hack(&mut self, output_path: &str) -> Result<(), Vec<Diagnostic>> {
+        let asm_name = if output_path == constants::STDIO_PATH {
+            "<stdout>".to_owned()
         }
-        // match bw.flush() {
-        //     Ok(_)   => (),
-        //     Err(_)  => println!("Failed to flush to file")
-        // }
+        else {
+            Path::new(output_path).with_extension("asm").to_string_lossy().into_owned()
+        };
+        let machine_code = assembler::assemble(&asm_name, &self.translation().asm())?;
 
+        write_output(output_path, &machine_code.to_hack_string()).map_err(|err| vec![err.into()])?;
+
+        return Ok(());
     }
-}
\ No newline at end of file
+}
+
+/// Reads a whole file, or stdin if the path is -
+pub fn read_input(input_path: &str) -> Result<String, TranslateError> {
+    if input_path == constants::STDIO_PATH {
+        let mut text = String::new();
+        io::stdin().read_to_string(&mut text).map_err(|e| TranslateError::io(constants::STDIN_NAME, e))?;
+        return Ok(text);
+    }
+
+    return read_to_string(input_path).map_err(|e| TranslateError::io(input_path, e));
+}
+
+/// Writes a whole file, or stdout if the path is -
+pub fn write_output(output_path: &str, contents: &str) -> Result<(), TranslateError> {
+    if output_path == constants::STDIO_PATH {
+        return io::stdout().write_all(contents.as_bytes()).map_err(|e| TranslateError::io("<stdout>", e));
+    }
+
+    let mut file = File::create(output_path).map_err(|e| TranslateError::io(output_path, e))?;
+
+    file.write_all(contents.as_bytes()).map_err(|e| TranslateError::io(output_path, e))?;
+
+    return Ok(());
+}
+
+/// Adds the path of every vm file in a directory to files, sorted by path
+/// 
+/// Subdirectories are searched too when recursive, adding an error for any that can't be read
+pub fn find_vm_files(dir_path: &str, recursive: bool, files: &mut Vec<String>, diagnostics: &mut Vec<Diagnostic>) -> Result<(), Diagnostic> {
+    let filenames = read_dir(dir_path).map_err(|e| TranslateError::io(dir_path, e))?;
+
+    // The filesystem lists files in any order, so sort them to always produce the same output
+    let mut entries = Vec::new();
+    for filename in filenames {
+        entries.push(filename.map_err(|e| TranslateError::io(dir_path, e))?.path());
+    }
+    entries.sort();
+
+    for entry in entries {
+        let file = entry.to_string_lossy().into_owned();
+
+        if entry.is_dir() {
+            // A directory that can't be read shouldn't hide errors in the others
+            if recursive {
+                if let Err(diagnostic) = find_vm_files(file.as_str(), recursive, files, diagnostics) {
+                    diagnostics.push(diagnostic);
+                }
+            }
+        }
+        else if entry.extension().is_some_and(|ext| ext == "vm") {
+            files.push(file);
+        }
+    }
+
+    return Ok(());
+}
@@ -0,0 +1,86 @@
+use crate::assembler;
+use crate::translator::{label_scopes, Translation};
+
+/// Writes a JSON source map mapping the generated assembly back to the VM code
+/// 
+/// "commands" holds every command in program order with its source file and line (null for
+/// generated code like the bootstrap), the function it belongs to (its module outside of any
+/// function), its VM code and the ROM addresses of its instructions (end exclusive)
+/// 
+/// "lines" holds every line of the .asm file in order with its ROM address (null for comments and
+/// labels) and the index of the command it was generated for
+/// 
+/// Ex:
+///     {
+///       "version": 1,
+///       "commands": [
+///         {"file": "Main.vm", "line": 2, "function": "Main.fibonacci", "vm": "push argument 0", "rom_start": 48, "rom_end": 57}
+///       ],
+///       "lines": [
+///         {"asm_line": 160, "rom": 48, "command": 0}
+///       ]
+///     }
+pub fn source_map(translation: &Translation) -> String {
+    let commands = translation.commands();
+    let rom_ranges = translation.rom_ranges();
+
+    let mut command_entries = Vec::new();
+    let mut line_entries = Vec::new();
+    let mut asm_line = 0;
+
+    for (command_i, (command, scope)) in commands.iter().zip(label_scopes(commands)).enumerate() {
+        let range = &rom_ranges[command_i];
+
+        let (file, line) = match command.span() {
+            Some(span)  => (json_string(&span.file), span.line.to_string()),
+            None        => ("null".to_owned(), "null".to_owned())
+        };
+        let vm_code = command.instruction().map_or("null".to_owned(), |instruction| json_string(&instruction.to_string()));
+
+        command_entries.push(format!(
+            "{{\"file\": {}, \"line\": {}, \"function\": {}, \"vm\": {}, \"rom_start\": {}, \"rom_end\": {}}}",
+            file, line, json_string(&scope), vm_code, range.start, range.end
+        ));
+
+        let mut rom_addr = range.start;
+        for asm in command.get_processed().into_iter().flatten() {
+            asm_line += 1;
+
+            let rom = if assembler::is_instruction(asm) {
+                rom_addr += 1;
+                (rom_addr - 1).to_string()
+            }
+            else {
+                "null".to_owned()
+            };
+
+            line_entries.push(format!("{{\"asm_line\": {}, \"rom\": {}, \"command\": {}}}", asm_line, rom, command_i));
+        }
+    }
+
+    let mut json = String::from("{\n  \"version\": 1,\n");
+    json.push_str(&format!("  \"commands\": [\n    {}\n  ],\n", command_entries.join(",\n    ")));
+    json.push_str(&format!("  \"lines\": [\n    {}\n  ]\n}}\n", line_entries.join(",\n    ")));
+
+    return json;
+}
+
+/// Quotes and escapes a string for JSON
+fn json_string(s: &str) -> String {
+    let mut quoted = String::from("\"");
+
+    for c in s.chars() {
+        match c {
+            '"'                 => quoted.push_str("\\\""),
+            '\\'                => quoted.push_str("\\\\"),
+            '\n'                => quoted.push_str("\\n"),
+            '\r'                => quoted.push_str("\\r"),
+            '\t'                => quoted.push_str("\\t"),
+            c if c < ' '        => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c                   => quoted.push(c)
+        }
+    }
+
+    quoted.push('"');
+    return quoted;
+}
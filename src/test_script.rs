@@ -0,0 +1,791 @@
+use std::fs::{read_to_string, File};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use crate::assembler;
+use crate::diagnostic::{Diagnostic, Span};
+use crate::emulator::{self, Cpu};
+use crate::error::TranslateError;
+use crate::interpreter::VmInterpreter;
+use crate::parser::Parser;
+use crate::translator::TranslateOptions;
+
+/// Output format used by output-list entries without one (%B1.16.1)
+const DEFAULT_FORMAT: (char, usize, usize, usize) = ('B', 1, 16, 1);
+
+/// Piece of a test script
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Text(String),
+    Symbol(char)
+}
+
+/// Value a script can read or set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Variable {
+    /// RAM[n], sp, local, argument, this, that and temp[i]
+    Ram(u16),
+    /// local[i], argument[i], this[i] and that[i], relative to the pointer at base
+    Indexed { base: u16, index: u16 },
+    A,
+    D,
+    Pc
+}
+
+/// Column of the output table
+#[derive(Debug, Clone)]
+struct OutputColumn {
+    name: String,
+    variable: Variable,
+    /// D (decimal), X (hex), B (binary) or S (left aligned decimal)
+    format: char,
+    pad_left: usize,
+    width: usize,
+    pad_right: usize
+}
+
+#[derive(Debug, Clone)]
+enum ScriptCommand {
+    /// Program to load, None loads the vm files in the script's directory
+    Load(Option<String>),
+    OutputFile(String),
+    CompareTo(String),
+    OutputList(Vec<OutputColumn>),
+    Set { variable: Variable, value: i16 },
+    /// First half of a clock cycle, doesn't do anything in the CPU emulator
+    Tick,
+    /// Second half of a clock cycle, executes an instruction
+    Tock,
+    TickTock,
+    VmStep,
+    Output,
+    Echo(String),
+    ClearEcho,
+    Repeat { count: u64, body: Vec<(ScriptCommand, Span)> }
+}
+
+/// Program a test script runs
+enum Machine {
+    /// Assembly on the CPU emulator
+    Cpu(Cpu),
+    /// VM code on the VM interpreter
+    Vm(Box<VmInterpreter>)
+}
+
+/// Result of a test script that ran to the end
+pub struct ScriptResult {
+    /// Every line written to the output file
+    pub output: Vec<String>,
+    /// File the output table was written to
+    pub output_path: Option<PathBuf>,
+    /// Compare file every output line matched
+    pub compare_path: Option<PathBuf>,
+    /// Text printed with echo
+    pub messages: Vec<String>,
+    /// Warnings raised while translating the loaded program
+    pub diagnostics: Vec<Diagnostic>
+}
+
+/// Runs a nand2tetris .tst script
+///
+/// .asm and .hack programs run on the CPU emulator, except that a .asm program with a .vm file
+/// of the same name (or named after the script's directory) is translated from the VM code
+/// first. .vm programs, or a load without a file, run on the VM interpreter
+///
+/// Every output line is compared with the compare file as it is written and the script stops at the
+/// first one that doesn't match, after writing the output file
+pub fn run_script(path: &str, options: TranslateOptions) -> Result<ScriptResult, Vec<Diagnostic>> {
+    let text = read_to_string(path).map_err(|e| vec![TranslateError::io(path, e).into()])?;
+    let commands = parse_script(path, &text).map_err(|d| vec![d])?;
+
+    let dir = Path::new(path).parent().map_or(PathBuf::from("."), |dir| {
+        if dir.as_os_str().is_empty() { PathBuf::from(".") } else { dir.to_path_buf() }
+    });
+
+    let mut runner = ScriptRunner {
+        dir,
+        options,
+        machine: None,
+        columns: Vec::new(),
+        compare: None,
+        result: ScriptResult {
+            output: Vec::new(),
+            output_path: None,
+            compare_path: None,
+            messages: Vec::new(),
+            diagnostics: Vec::new()
+        }
+    };
+
+    let status = runner.run_all(&commands);
+
+    if let Some(output_path) = &runner.result.output_path {
+        let mut output = String::new();
+        for line in runner.result.output.iter() {
+            output.push_str(line);
+            output.push('\n');
+        }
+
+        let path_str = output_path.to_string_lossy();
+        let mut out_file = File::create(output_path).map_err(|e| vec![TranslateError::io(&path_str, e).into()])?;
+        out_file.write_all(output.as_bytes()).map_err(|e| vec![TranslateError::io(&path_str, e).into()])?;
+    }
+
+    status?;
+
+    return Ok(runner.result);
+}
+
+/// State of a script while it runs
+struct ScriptRunner {
+    /// Directory file names in the script are relative to
+    dir: PathBuf,
+    options: TranslateOptions,
+    machine: Option<Machine>,
+    columns: Vec<OutputColumn>,
+    /// Name and lines of the compare file
+    compare: Option<(String, Vec<String>)>,
+    result: ScriptResult
+}
+
+impl ScriptRunner {
+    fn run_all(&mut self, commands: &[(ScriptCommand, Span)]) -> Result<(), Vec<Diagnostic>> {
+        for (command, span) in commands.iter() {
+            self.run(command, span)?;
+        }
+
+        return Ok(());
+    }
+
+    fn run(&mut self, command: &ScriptCommand, span: &Span) -> Result<(), Vec<Diagnostic>> {
+        let error = |message: &str| vec![Diagnostic::error(Some(span.clone()), TranslateError::InvalidScript(message.to_owned()))];
+
+        match command {
+            ScriptCommand::Load(file) => self.load(file.as_deref())?,
+            ScriptCommand::OutputFile(file) => self.result.output_path = Some(self.dir.join(file)),
+            ScriptCommand::CompareTo(file) => {
+                let cmp_path = self.dir.join(file);
+                let name = cmp_path.to_string_lossy().into_owned();
+                let text = read_to_string(&cmp_path).map_err(|e| vec![TranslateError::io(&name, e).into()])?;
+
+                self.compare = Some((name, text.lines().map(str::to_owned).collect()));
+                self.result.compare_path = Some(cmp_path);
+            },
+            ScriptCommand::OutputList(columns) => {
+                self.columns = columns.clone();
+                self.write_line(format_header(&self.columns))?;
+            },
+            ScriptCommand::Set { variable, value } => {
+                let machine = self.machine.as_mut().ok_or_else(|| error("no program has been loaded"))?;
+                set_variable(machine, *variable, *value).map_err(|message| error(&message))?;
+            },
+            ScriptCommand::Tick => {
+                if !matches!(self.machine, Some(Machine::Cpu(_))) {
+                    return Err(error("tick needs an assembly program to be loaded"));
+                }
+            },
+            ScriptCommand::Tock | ScriptCommand::TickTock => match self.machine.as_mut() {
+                Some(Machine::Cpu(cpu)) => cpu.step(),
+                _ => return Err(error("ticktock needs an assembly program to be loaded"))
+            },
+            ScriptCommand::VmStep => match self.machine.as_mut() {
+                Some(Machine::Vm(interpreter)) => interpreter.step().map_err(|d| vec![d])?,
+                _ => return Err(error("vmstep needs a VM program to be loaded"))
+            },
+            ScriptCommand::Output => {
+                let machine = self.machine.as_ref().ok_or_else(|| error("no program has been loaded"))?;
+
+                let mut line = String::from("|");
+                for column in self.columns.iter() {
+                    let value = get_variable(machine, column.variable).map_err(|message| error(&message))?;
+                    line.push_str(&format!(
+                        "{}{}{}|",
+                        " ".repeat(column.pad_left),
+                        format_value(value, column.format, column.width),
+                        " ".repeat(column.pad_right)
+                    ));
+                }
+                self.write_line(line)?;
+            },
+            ScriptCommand::Echo(text) => self.result.messages.push(text.clone()),
+            ScriptCommand::ClearEcho => {},
+            ScriptCommand::Repeat { count, body } => {
+                for _ in 0..*count {
+                    self.run_all(body)?;
+                }
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// Adds a line to the output and checks it against the compare file
+    fn write_line(&mut self, line: String) -> Result<(), Vec<Diagnostic>> {
+        let line_i = self.result.output.len();
+        self.result.output.push(line);
+
+        let (name, lines) = match &self.compare {
+            Some(compare)   => compare,
+            None            => return Ok(())
+        };
+
+        let actual = &self.result.output[line_i];
+        let expected = lines.get(line_i).map_or("", String::as_str);
+
+        if !lines_match(expected, actual) {
+            let span = Span::new(name, line_i + 1, 1, expected.chars().count() + 1);
+            return Err(vec![Diagnostic::error(Some(span), TranslateError::ComparisonFailure {
+                expected: expected.to_owned(),
+                actual: actual.clone()
+            })]);
+        }
+
+        return Ok(());
+    }
+
+    /// Loads a program onto the CPU emulator or the VM interpreter
+    fn load(&mut self, file: Option<&str>) -> Result<(), Vec<Diagnostic>> {
+        let file = match file {
+            Some(file)  => file,
+            None        => return self.load_vm(&self.dir.clone())
+        };
+
+        let path = self.dir.join(file);
+        let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+
+        if extension == "vm" || path.is_dir() {
+            return self.load_vm(&path);
+        }
+
+        // Test the translator on the VM code the assembly would have been generated from
+        let vm_file = path.with_extension("vm");
+        let dir_name = self.dir.canonicalize().ok().and_then(|dir| dir.file_name().map(|name| name.to_owned()));
+        let is_dir_program = !path.exists() && path.file_stem() == dir_name.as_deref();
+
+        let rom = if extension == "asm" && (vm_file.is_file() || is_dir_program) {
+            let vm_path = if vm_file.is_file() { vm_file } else { self.dir.clone() };
+            let mut options = self.options.clone();
+            options.executable = true;
+
+            let mut parser = Parser::new(&vm_path.to_string_lossy(), options)?;
+            self.result.diagnostics.extend(parser.take_diagnostics());
+            assembler::assemble(&path.to_string_lossy(), &parser.translation().asm())?.instructions
+        }
+        else {
+            emulator::load_rom(&path.to_string_lossy(), self.options.clone())?
+        };
+
+        self.machine = Some(Machine::Cpu(Cpu::new(rom)));
+        return Ok(());
+    }
+
+    /// Loads VM code onto the VM interpreter, starting at the entry point if there is one
+    fn load_vm(&mut self, path: &Path) -> Result<(), Vec<Diagnostic>> {
+        // The VM emulator starts in the entry point itself instead of calling it
+        let mut options = self.options.clone();
+        options.bootstrap = Some(false);
+
+        let mut parser = Parser::new(&path.to_string_lossy(), options)?;
+        self.result.diagnostics.extend(parser.take_diagnostics());
+
+        let mut interpreter = VmInterpreter::new(parser.translation());
+        if let Some(entry) = interpreter.function_index(&self.options.entry_point) {
+            interpreter.set_pc(entry);
+        }
+
+        self.machine = Some(Machine::Vm(Box::new(interpreter)));
+        return Ok(());
+    }
+}
+
+/// Checks an output line against a compare line, where * in the compare line matches anything
+fn lines_match(expected: &str, actual: &str) -> bool {
+    if expected.chars().count() != actual.chars().count() {
+        return false;
+    }
+
+    return expected.chars().zip(actual.chars()).all(|(e, a)| e == '*' || e == a);
+}
+
+/// Header line of the output table, with each name centred in its column
+fn format_header(columns: &[OutputColumn]) -> String {
+    let mut header = String::from("|");
+
+    for column in columns.iter() {
+        let space = column.pad_left + column.width + column.pad_right;
+        let name: String = column.name.chars().take(space).collect();
+        let left = (space - name.len()) / 2;
+        let right = space - left - name.len();
+        header.push_str(&format!("{}{}{}|", " ".repeat(left), name, " ".repeat(right)));
+    }
+
+    return header;
+}
+
+/// Formats a value for the output table
+fn format_value(value: i16, format: char, width: usize) -> String {
+    let digits = match format {
+        'X' => format!("{:04X}", value as u16),
+        'B' => format!("{:016b}", value as u16),
+        'S' => return format!("{:<width$}", value, width = width),
+        _   => return format!("{:>width$}", value, width = width)
+    };
+
+    // Binary and hex keep their lowest digits
+    let skip = digits.len().saturating_sub(width);
+    return format!("{:>width$}", &digits[skip..], width = width);
+}
+
+fn ram_addr(machine: &Machine, variable: Variable) -> Option<u16> {
+    let ram = match machine {
+        Machine::Cpu(cpu)           => cpu.ram(),
+        Machine::Vm(interpreter)    => interpreter.ram()
+    };
+
+    match variable {
+        Variable::Ram(addr)                 => Some(addr),
+        Variable::Indexed { base, index }   => Some((ram[base as usize] as u16).wrapping_add(index) & 0x7fff),
+        _                                   => None
+    }
+}
+
+fn get_variable(machine: &Machine, variable: Variable) -> Result<i16, String> {
+    if let Some(addr) = ram_addr(machine, variable) {
+        match machine {
+            Machine::Cpu(cpu)           => return Ok(cpu.ram()[addr as usize]),
+            Machine::Vm(interpreter)    => return Ok(interpreter.ram()[addr as usize])
+        }
+    }
+
+    match (machine, variable) {
+        (Machine::Cpu(cpu), Variable::A)    => Ok(cpu.a()),
+        (Machine::Cpu(cpu), Variable::D)    => Ok(cpu.d()),
+        (Machine::Cpu(cpu), Variable::Pc)   => Ok(cpu.pc() as i16),
+        _                                   => Err("A, D and PC are only available for assembly programs".to_owned())
+    }
+}
+
+fn set_variable(machine: &mut Machine, variable: Variable, value: i16) -> Result<(), String> {
+    if let Some(addr) = ram_addr(machine, variable) {
+        match machine {
+            Machine::Cpu(cpu)           => cpu.set_ram(addr, value),
+            Machine::Vm(interpreter)    => interpreter.set_ram(addr, value)
+        }
+        return Ok(());
+    }
+
+    match (machine, variable) {
+        (Machine::Cpu(cpu), Variable::Pc)   => cpu.set_pc(value as u16),
+        (Machine::Cpu(_), _)                => return Err("A and D can't be set".to_owned()),
+        _                                   => return Err("PC is only available for assembly programs".to_owned())
+    }
+
+    return Ok(());
+}
+
+/// Splits a script into tokens along with the span of each
+fn tokenize(name: &str, text: &str) -> Result<Vec<(Token, Span)>, Diagnostic> {
+    let mut tokens = Vec::new();
+    let mut in_block_comment = false;
+
+    for (line_i, line) in text.lines().enumerate() {
+        let chars: Vec<char> = line.chars().collect();
+        let span = |start: usize, end: usize| Span::new(name, line_i + 1, start + 1, end + 1);
+        let mut i = 0;
+
+        while i < chars.len() {
+            let c = chars[i];
+            let next = chars.get(i + 1).copied();
+
+            if in_block_comment {
+                if c == '*' && next == Some('/') {
+                    in_block_comment = false;
+                    i += 1;
+                }
+                i += 1;
+            }
+            else if c == '/' && next == Some('/') {
+                break;
+            }
+            else if c == '/' && next == Some('*') {
+                in_block_comment = true;
+                i += 2;
+            }
+            else if c.is_whitespace() {
+                i += 1;
+            }
+            else if c == '"' {
+                let end = chars[i + 1..].iter().position(|&c| c == '"').map(|end| end + i + 1).ok_or_else(|| {
+                    Diagnostic::error(Some(span(i, chars.len())), TranslateError::InvalidScript("unterminated string".to_owned()))
+                })?;
+
+                tokens.push((Token::Text(chars[i + 1..end].iter().collect()), span(i, end + 1)));
+                i = end + 1;
+            }
+            else if ",;!{}".contains(c) {
+                tokens.push((Token::Symbol(c), span(i, i + 1)));
+                i += 1;
+            }
+            else {
+                let start = i;
+                while i < chars.len() && !chars[i].is_whitespace() && !",;!{}\"".contains(chars[i]) {
+                    i += 1;
+                }
+                tokens.push((Token::Word(chars[start..i].iter().collect()), span(start, i)));
+            }
+        }
+    }
+
+    return Ok(tokens);
+}
+
+/// Parses a whole script into commands
+fn parse_script(name: &str, text: &str) -> Result<Vec<(ScriptCommand, Span)>, Diagnostic> {
+    let tokens = tokenize(name, text)?;
+    let mut pos = 0;
+
+    let commands = parse_block(&tokens, &mut pos)?;
+
+    if let Some((_, span)) = tokens.get(pos) {
+        return Err(script_error(span, "unexpected '}'"));
+    }
+
+    return Ok(commands);
+}
+
+fn script_error(span: &Span, message: &str) -> Diagnostic {
+    return Diagnostic::error(Some(span.clone()), TranslateError::InvalidScript(message.to_owned()));
+}
+
+/// Parses commands until the end of the script or the } closing the block
+fn parse_block(tokens: &[(Token, Span)], pos: &mut usize) -> Result<Vec<(ScriptCommand, Span)>, Diagnostic> {
+    let mut commands = Vec::new();
+
+    while let Some((token, span)) = tokens.get(*pos) {
+        if *token == Token::Symbol('}') {
+            break;
+        }
+
+        // Separators between commands
+        if matches!(token, Token::Symbol(_)) {
+            *pos += 1;
+            continue;
+        }
+
+        // Everything up to the next separator belongs to this command
+        let mut args = Vec::new();
+        while let Some((token, span)) = tokens.get(*pos) {
+            if matches!(token, Token::Symbol(_)) {
+                break;
+            }
+            args.push((token.clone(), span.clone()));
+            *pos += 1;
+        }
+
+        let command = parse_command(&args)?;
+
+        if let ScriptCommand::Repeat { count, .. } = command {
+            if tokens.get(*pos).map(|(token, _)| token) != Some(&Token::Symbol('{')) {
+                return Err(script_error(span, "repeat must be followed by a block"));
+            }
+            *pos += 1;
+
+            let body = parse_block(tokens, pos)?;
+
+            if tokens.get(*pos).map(|(token, _)| token) != Some(&Token::Symbol('}')) {
+                return Err(script_error(span, "repeat block is never closed"));
+            }
+            *pos += 1;
+
+            commands.push((ScriptCommand::Repeat { count, body }, span.clone()));
+        }
+        else {
+            commands.push((command, span.clone()));
+        }
+    }
+
+    return Ok(commands);
+}
+
+/// Parses the words of a single command
+fn parse_command(args: &[(Token, Span)]) -> Result<ScriptCommand, Diagnostic> {
+    let (keyword, span) = match &args[0] {
+        (Token::Word(word), span)   => (word.as_str(), span),
+        (_, span)                   => return Err(script_error(span, "expected a command"))
+    };
+
+    let word = |i: usize, what: &str| -> Result<&str, Diagnostic> {
+        match args.get(i) {
+            Some((Token::Word(word), _))    => Ok(word.as_str()),
+            Some((_, span))                 => Err(script_error(span, &format!("expected {}", what))),
+            None                            => Err(script_error(span, &format!("'{}' is missing its {}", keyword, what)))
+        }
+    };
+
+    let arg_count = match keyword {
+        "load"                                                      => args.len().clamp(1, 2),
+        "output-file" | "compare-to" | "echo"                       => 2,
+        "set"                                                       => 3,
+        "repeat"                                                    => 2,
+        "output-list"                                               => args.len(),
+        _                                                           => 1
+    };
+
+    if let Some((_, span)) = args.get(arg_count) {
+        return Err(script_error(span, "unexpected argument"));
+    }
+
+    let command = match keyword {
+        "load"          => ScriptCommand::Load(if args.len() > 1 { Some(word(1, "file")?.to_owned()) } else { None }),
+        "output-file"   => ScriptCommand::OutputFile(word(1, "file")?.to_owned()),
+        "compare-to"    => ScriptCommand::CompareTo(word(1, "file")?.to_owned()),
+        "output-list"   => {
+            let mut columns = Vec::new();
+            for (i, (_, span)) in args.iter().enumerate().skip(1) {
+                columns.push(parse_column(word(i, "variable")?, span)?);
+            }
+            ScriptCommand::OutputList(columns)
+        },
+        "set"           => {
+            let variable = parse_variable(word(1, "variable")?).ok_or_else(|| script_error(&args[1].1, "unknown variable"))?;
+            let value = parse_value(word(2, "value")?).ok_or_else(|| script_error(&args[2].1, "invalid value"))?;
+            ScriptCommand::Set { variable, value }
+        },
+        "tick"          => ScriptCommand::Tick,
+        "tock"          => ScriptCommand::Tock,
+        "ticktock"      => ScriptCommand::TickTock,
+        "vmstep"        => ScriptCommand::VmStep,
+        "output"        => ScriptCommand::Output,
+        "echo"          => match args.get(1) {
+            Some((Token::Text(text), _)) | Some((Token::Word(text), _)) => ScriptCommand::Echo(text.clone()),
+            _ => return Err(script_error(span, "'echo' is missing its text"))
+        },
+        "clear-echo"    => ScriptCommand::ClearEcho,
+        "repeat"        => {
+            let count = word(1, "count")?;
+            let count = count.parse().map_err(|_| script_error(&args[1].1, "repeat count must be a number"))?;
+            ScriptCommand::Repeat { count, body: Vec::new() }
+        },
+        _               => return Err(script_error(span, &format!("unsupported command '{}'", keyword)))
+    };
+
+    return Ok(command);
+}
+
+/// Parses an output-list entry like RAM[0]%D1.6.1
+fn parse_column(entry: &str, span: &Span) -> Result<OutputColumn, Diagnostic> {
+    let (name, format) = entry.split_once('%').unwrap_or((entry, ""));
+    let variable = parse_variable(name).ok_or_else(|| script_error(span, &format!("unknown variable '{}'", name)))?;
+
+    let (format, pad_left, width, pad_right) = if format.is_empty() {
+        DEFAULT_FORMAT
+    }
+    else {
+        let bad_format = || script_error(span, &format!("invalid output format '%{}'", format));
+
+        let format_char = format.chars().next().filter(|c| "DXBS".contains(*c)).ok_or_else(bad_format)?;
+        let sizes: Vec<usize> = format[1..].split('.').map(|n| n.parse().map_err(|_| bad_format())).collect::<Result<_, _>>()?;
+
+        if sizes.len() != 3 {
+            return Err(bad_format());
+        }
+
+        (format_char, sizes[0], sizes[1], sizes[2])
+    };
+
+    return Ok(OutputColumn { name: name.to_owned(), variable, format, pad_left, width, pad_right });
+}
+
+/// Parses a variable name like RAM[256], sp or local[2]
+fn parse_variable(name: &str) -> Option<Variable> {
+    let (base, index) = match name.split_once('[') {
+        Some((base, rest)) => (base, Some(rest.strip_suffix(']')?.parse::<u16>().ok()?)),
+        None => (name, None)
+    };
+
+    let variable = match (base, index) {
+        ("RAM", Some(addr))         => Variable::Ram(addr & 0x7fff),
+        ("sp", None)                => Variable::Ram(0),
+        ("local", None)             => Variable::Ram(1),
+        ("argument", None)          => Variable::Ram(2),
+        ("this", None)              => Variable::Ram(3),
+        ("that", None)              => Variable::Ram(4),
+        ("local", Some(index))      => Variable::Indexed { base: 1, index },
+        ("argument", Some(index))   => Variable::Indexed { base: 2, index },
+        ("this", Some(index))       => Variable::Indexed { base: 3, index },
+        ("that", Some(index))       => Variable::Indexed { base: 4, index },
+        ("temp", Some(index)) if index < 8 => Variable::Ram(5 + index),
+        ("A", None)                 => Variable::A,
+        ("D", None)                 => Variable::D,
+        ("PC", None)                => Variable::Pc,
+        _                           => return None
+    };
+
+    return Some(variable);
+}
+
+/// Parses a value to set, either decimal or with a %D, %X or %B prefix
+fn parse_value(value: &str) -> Option<i16> {
+    let parsed = if let Some(hex) = value.strip_prefix("%X") {
+        i32::from_str_radix(hex, 16).ok()?
+    }
+    else if let Some(binary) = value.strip_prefix("%B") {
+        i32::from_str_radix(binary, 2).ok()?
+    }
+    else {
+        value.strip_prefix("%D").unwrap_or(value).parse::<i32>().ok()?
+    };
+
+    if !(-32768..=65535).contains(&parsed) {
+        return None;
+    }
+
+    return Some(parsed as u16 as i16);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs a script on an assembly program already loaded on the CPU emulator, returning the output table
+    fn run_inline(asm: &[&str], script: &str, compare: Option<&str>) -> Result<Vec<String>, Vec<Diagnostic>> {
+        let rom = assembler::assemble("Test.asm", asm)?.instructions;
+
+        let mut runner = ScriptRunner {
+            dir: PathBuf::from("."),
+            options: TranslateOptions::default(),
+            machine: Some(Machine::Cpu(Cpu::new(rom))),
+            columns: Vec::new(),
+            compare: compare.map(|text| ("Test.cmp".to_owned(), text.lines().map(str::to_owned).collect())),
+            result: ScriptResult {
+                output: Vec::new(),
+                output_path: None,
+                compare_path: None,
+                messages: Vec::new(),
+                diagnostics: Vec::new()
+            }
+        };
+
+        runner.run_all(&parse_script("Test.tst", script).map_err(|d| vec![d])?)?;
+
+        return Ok(runner.result.output);
+    }
+
+    /// Message of a script error
+    fn script_message(diagnostic: Diagnostic) -> String {
+        return diagnostic.error.to_string();
+    }
+
+    const SCRIPT: &str = "\
+        // Adds RAM[0] to RAM[1]
+        output-list RAM[0]%D2.6.2 RAM[1]%X1.4.1 D%B1.16.1 A%S1.6.1;
+        set RAM[0] 7, set RAM[1] %XFF, /* hex */
+        repeat 4 {
+            ticktock;
+        }
+        output;";
+
+    const TABLE: &str = "\
+|  RAM[0]  |RAM[1]|        D         |   A    |
+|       7  | 0106 | 0000000000000111 | 1      |";
+
+    #[test]
+    fn writes_output_table() {
+        let output = run_inline(&["@0", "D=M", "@1", "M=D+M"], SCRIPT, None).unwrap();
+
+        assert_eq!(output.join("\n"), TABLE);
+    }
+
+    #[test]
+    fn compares_with_wildcards() {
+        let compare = TABLE.replace("0106", "01**");
+        assert!(run_inline(&["@0", "D=M", "@1", "M=D+M"], SCRIPT, Some(&compare)).is_ok());
+
+        let compare = TABLE.replace("0106", "0107");
+        let diagnostics = run_inline(&["@0", "D=M", "@1", "M=D+M"], SCRIPT, Some(&compare)).err().unwrap();
+        assert_eq!(diagnostics[0].span.as_ref().unwrap().line, 2);
+        assert!(matches!(diagnostics[0].error, TranslateError::ComparisonFailure { .. }));
+    }
+
+    #[test]
+    fn matches_lines() {
+        assert!(lines_match("|  7 |", "|  7 |"));
+        assert!(lines_match("| ** |", "| 12 |"));
+        assert!(!lines_match("|  7 |", "|  8 |"));
+        // Trailing text isn't ignored
+        assert!(!lines_match("|  7 |", "|  7 | "));
+    }
+
+    #[test]
+    fn formats_values() {
+        assert_eq!(format_value(-1, 'D', 6), "    -1");
+        assert_eq!(format_value(-1, 'S', 6), "-1    ");
+        assert_eq!(format_value(-1, 'X', 4), "FFFF");
+        assert_eq!(format_value(255, 'X', 2), "FF");
+        assert_eq!(format_value(5, 'B', 4), "0101");
+        assert_eq!(format_value(5, 'B', 16), "0000000000000101");
+        assert_eq!(format_value(123456_i32 as i16, 'D', 3), "-7616");
+    }
+
+    #[test]
+    fn centres_header_names() {
+        let span = Span::new("Test.tst", 1, 1, 1);
+        let columns: Vec<OutputColumn> = ["RAM[256]%D1.6.1", "sp%D1.6.1", "RAM[0]%D0.3.0"].iter()
+            .map(|entry| parse_column(entry, &span).unwrap())
+            .collect();
+
+        // Odd space goes to the right and names too long for their column are cut
+        assert_eq!(format_header(&columns), "|RAM[256]|   sp   |RAM|");
+    }
+
+    #[test]
+    fn parses_columns() {
+        let span = Span::new("Test.tst", 1, 1, 1);
+
+        let column = parse_column("local[2]%X3.4.0", &span).unwrap();
+        assert_eq!(column.variable, Variable::Indexed { base: 1, index: 2 });
+        assert_eq!((column.format, column.pad_left, column.width, column.pad_right), ('X', 3, 4, 0));
+
+        let column = parse_column("temp[1]", &span).unwrap();
+        assert_eq!(column.variable, Variable::Ram(6));
+        assert_eq!((column.format, column.pad_left, column.width, column.pad_right), DEFAULT_FORMAT);
+
+        assert_eq!(script_message(parse_column("temp[8]%D1.6.1", &span).unwrap_err()), "unknown variable 'temp[8]'");
+        assert_eq!(script_message(parse_column("sp%Q1.6.1", &span).unwrap_err()), "invalid output format '%Q1.6.1'");
+        assert_eq!(script_message(parse_column("sp%D1.6", &span).unwrap_err()), "invalid output format '%D1.6'");
+    }
+
+    #[test]
+    fn tokenizes_scripts() {
+        let tokens = tokenize("Test.tst", "echo \"a b\"; /* skipped\n still skipped */ set sp 256, // end\n").unwrap();
+        let tokens: Vec<Token> = tokens.into_iter().map(|(token, _)| token).collect();
+
+        assert_eq!(tokens, vec![
+            Token::Word("echo".to_owned()),
+            Token::Text("a b".to_owned()),
+            Token::Symbol(';'),
+            Token::Word("set".to_owned()),
+            Token::Word("sp".to_owned()),
+            Token::Word("256".to_owned()),
+            Token::Symbol(',')
+        ]);
+
+        let error = tokenize("Test.tst", "echo \"open").unwrap_err();
+        assert_eq!(error.span.as_ref().unwrap().start_col, 6);
+        assert_eq!(script_message(error), "unterminated string");
+    }
+
+    #[test]
+    fn reports_script_errors() {
+        let error = |script: &str| script_message(parse_script("Test.tst", script).unwrap_err());
+
+        assert_eq!(error("repeat 2; ticktock;"), "repeat must be followed by a block");
+        assert_eq!(error("repeat 2 { ticktock;"), "repeat block is never closed");
+        assert_eq!(error("ticktock; }"), "unexpected '}'");
+        assert_eq!(error("set RAM[0]"), "'set' is missing its value");
+        assert_eq!(error("set RAM[0] 70000"), "invalid value");
+        assert_eq!(error("output now"), "unexpected argument");
+        assert_eq!(error("tick-tock"), "unsupported command 'tick-tock'");
+    }
+}
@@ -0,0 +1,603 @@
+use core::fmt;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::path::Path;
+
+use crate::assembler;
+use crate::codegen::CodeGen;
+use crate::constants;
+use crate::diagnostic::{Diagnostic, Span};
+use crate::error::TranslateError;
+use crate::instruction::VmInstruction;
+use crate::optimizer;
+use crate::parser::Command;
+
+/// A named piece of VM code to translate
+pub struct Source {
+    /// File name or path of the source, used in diagnostics and for static variable names
+    pub name: String,
+    /// Module name used for static variables instead of the one taken from the name
+    pub module: Option<String>,
+    /// VM code
+    pub text: String
+}
+
+impl Source {
+    pub fn new(name: &str, text: &str) -> Self {
+        return Source {
+            name: name.to_owned(),
+            module: None,
+            text: text.to_owned()
+        };
+    }
+
+    /// Source whose name doesn't give its module, like code read from stdin
+    pub fn with_module(name: &str, module: &str, text: &str) -> Self {
+        return Source {
+            name: name.to_owned(),
+            module: Some(module.to_owned()),
+            text: text.to_owned()
+        };
+    }
+
+    /// Name of the module (file name without extension) used for static variables
+    /// 
+    /// Only the last extension is removed, so Main.test.vm is the module Main.test
+    pub fn module_name(&self) -> &str {
+        if let Some(module) = &self.module {
+            return module;
+        }
+
+        return Path::new(&self.name).file_stem().and_then(|stem| stem.to_str()).unwrap_or("");
+    }
+}
+
+/// Settings controlling how VM code is translated
+#[derive(Clone)]
+pub struct TranslateOptions {
+    /// Whether to start the program with the bootstrap code, None lets the front end decide
+    pub bootstrap: Option<bool>,
+    /// Value the bootstrap code sets the stack pointer to
+    pub initial_sp: u16,
+    /// Function the bootstrap code calls
+    pub entry_point: String,
+    /// Whether to run the peephole optimizer over the generated assembly
+    pub optimize: bool,
+    /// Whether to make the program smaller by jumping to routines shared between commands
+    pub optimize_size: bool,
+    /// Whether the program is going to be assembled and run, which makes calls to functions that
+    /// are never declared errors instead of warnings
+    pub executable: bool
+}
+
+impl Default for TranslateOptions {
+    fn default() -> Self {
+        return TranslateOptions {
+            bootstrap: None,
+            initial_sp: constants::DEFAULT_SP,
+            entry_point: constants::DEFAULT_ENTRY_POINT.to_owned(),
+            optimize: false,
+            optimize_size: false,
+            executable: false
+        };
+    }
+}
+
+/// Translates in-memory VM sources into Hack assembly
+/// 
+/// Ex:
+///     let translation = Translator::new()
+///         .add_source("Main.vm", "push constant 7")
+///         .translate()?;
+///     let asm = translation.to_asm_string();
+#[derive(Default)]
+pub struct Translator {
+    sources: Vec<Source>,
+    options: TranslateOptions
+}
+
+impl Translator {
+    pub fn new() -> Self {
+        return Translator::default();
+    }
+
+    pub fn with_options(options: TranslateOptions) -> Self {
+        return Translator {
+            sources: Vec::new(),
+            options
+        };
+    }
+
+    /// Settings the sources will be translated with
+    pub fn options(&self) -> &TranslateOptions {
+        return &self.options;
+    }
+
+    /// Adds a source to the end of the program
+    pub fn add_source(&mut self, name: &str, text: &str) -> &mut Self {
+        self.sources.push(Source::new(name, text));
+        return self;
+    }
+
+    /// Adds a source with its own module name to the end of the program
+    pub fn add_module_source(&mut self, name: &str, module: &str, text: &str) -> &mut Self {
+        self.sources.push(Source::with_module(name, module, text));
+        return self;
+    }
+
+    /// Sets whether the bootstrap code is written before the sources
+    pub fn bootstrap(&mut self, bootstrap: bool) -> &mut Self {
+        self.options.bootstrap = Some(bootstrap);
+        return self;
+    }
+
+    /// Sets the value the bootstrap code initializes the stack pointer to
+    pub fn initial_sp(&mut self, initial_sp: u16) -> &mut Self {
+        self.options.initial_sp = initial_sp;
+        return self;
+    }
+
+    /// Sets the function the bootstrap code calls
+    pub fn entry_point(&mut self, entry_point: &str) -> &mut Self {
+        self.options.entry_point = entry_point.to_owned();
+        return self;
+    }
+
+    /// Sets whether the peephole optimizer runs over the generated assembly
+    pub fn optimize(&mut self, optimize: bool) -> &mut Self {
+        self.options.optimize = optimize;
+        return self;
+    }
+
+    /// Sets whether the program is going to be run, failing on calls to undeclared functions
+    pub fn executable(&mut self, executable: bool) -> &mut Self {
+        self.options.executable = executable;
+        return self;
+    }
+
+    /// Sets whether commands jump to routines shared between them instead of inlining their code
+    pub fn optimize_size(&mut self, optimize_size: bool) -> &mut Self {
+        self.options.optimize_size = optimize_size;
+        return self;
+    }
+
+    /// Translates every source in order
+    /// 
+    /// The bootstrap code is only written if it was turned on explicitly
+    /// 
+    /// Fails with every diagnostic found, sorted by file and line, if any of them is an error
+    pub fn translate(&self) -> Result<Translation, Vec<Diagnostic>> {
+        let mut codegen = CodeGen::new();
+        codegen.set_share_code(self.options.optimize_size);
+        let mut commands = Vec::new();
+        let mut diagnostics = Vec::new();
+
+        let bootstrap = self.options.bootstrap.unwrap_or(false);
+
+        if bootstrap {
+            if self.options.initial_sp > constants::MAX_CONSTANT {
+                return Err(vec![Diagnostic::from(TranslateError::InvalidOption(format!(
+                    "initial stack pointer {} is larger than {}", self.options.initial_sp, constants::MAX_CONSTANT
+                )))]);
+            }
+
+            if !assembler::is_symbol(&self.options.entry_point) {
+                return Err(vec![Diagnostic::from(TranslateError::InvalidOption(format!(
+                    "'{}' is not a valid entry point function name", self.options.entry_point
+                )))]);
+            }
+
+            commands.push(Command::from_asm(codegen.write_init(self.options.initial_sp), constants::BOOTSTRAP_MODULE));
+            let sysinit = Command::new(&format!("call {} 0", self.options.entry_point), constants::BOOTSTRAP_MODULE, None)
+                .map_err(|d| vec![d])?;
+            commands.push(sysinit);
+        }
+
+        // Parse every source first
+        for source in self.sources.iter() {
+            for (line_i, line) in source.text.lines().enumerate() {
+                let span = Span::new(&source.name, line_i + 1, 1, line.chars().count() + 1);
+                // Keep going after a bad command so every error gets reported
+                let mut command = match Command::new(line, source.module_name(), Some(span)) {
+                    Ok(command) => command,
+                    Err(diagnostic) => {
+                        diagnostics.push(diagnostic);
+                        continue;
+                    }
+                };
+
+                diagnostics.extend(command.take_warnings());
+
+                if command.has_command() {
+                    commands.push(command);
+                }
+            }
+        }
+
+        diagnostics.extend(check_labels(&commands));
+        diagnostics.extend(check_functions(&commands));
+        diagnostics.extend(check_calls(&commands, self.options.executable));
+
+        diagnostics.sort_by(|a, b| a.span.cmp(&b.span));
+
+        if diagnostics.iter().any(Diagnostic::is_error) {
+            return Err(diagnostics);
+        }
+
+        // Then generate the assembly for each instruction
+        for command in commands.iter_mut() {
+            if let Some(instruction) = command.instruction().cloned() {
+                codegen.set_program_name(command.program_name());
+
+                match codegen.generate(&instruction) {
+                    Ok(asm) => command.set_processed(asm),
+                    Err(e)  => diagnostics.push(Diagnostic::error(command.span().cloned(), e))
+                }
+            }
+        }
+
+        let runtime = codegen.write_runtime();
+        if !runtime.is_empty() {
+            commands.insert(0, Command::from_asm(runtime, constants::RUNTIME_MODULE));
+        }
+
+        // Generated labels can still clash with user labels and function names
+        diagnostics.extend(check_label_collisions(&commands));
+
+        if diagnostics.iter().any(Diagnostic::is_error) {
+            diagnostics.sort_by(|a, b| a.span.cmp(&b.span));
+            return Err(diagnostics);
+        }
+
+        let fused = if self.options.optimize { optimizer::optimize(&mut commands) } else { HashSet::new() };
+
+        let size_report = SizeReport::new(&commands);
+        if size_report.total > constants::ROM_SIZE {
+            diagnostics.push(Diagnostic::from(TranslateError::RomOverflow {
+                size: size_report.total,
+                max: constants::ROM_SIZE,
+                largest: size_report.functions.iter().take(3).cloned().collect()
+            }));
+            return Err(diagnostics);
+        }
+
+        let initial_sp = if bootstrap { Some(self.options.initial_sp) } else { None };
+
+        return Ok(Translation { commands, diagnostics, initial_sp, fused });
+    }
+}
+
+/// Finds the function each command belongs to, which is what branching labels are scoped to
+/// 
+/// Commands outside of any function are scoped to their module, like in the code generator
+pub(crate) fn label_scopes(commands: &[Command]) -> Vec<String> {
+    let mut scopes = Vec::new();
+
+    let mut module = "";
+    let mut scope = String::new();
+
+    for command in commands.iter() {
+        let module_name = command.program_name();
+
+        if module_name != module {
+            module = module_name;
+            scope = module_name.to_string();
+        }
+
+        if let Some(VmInstruction::Function { name, .. }) = command.instruction() {
+            scope = name.clone();
+        }
+
+        scopes.push(scope.clone());
+    }
+
+    return scopes;
+}
+
+/// Checks that every goto and if-goto targets a label declared in the same function
+fn check_labels(commands: &[Command]) -> Vec<Diagnostic> {
+    let mut declared: HashMap<String, HashSet<&str>> = HashMap::new();
+    let mut jumps = Vec::new();
+
+    for (command, scope) in commands.iter().zip(label_scopes(commands)) {
+        match command.instruction() {
+            Some(VmInstruction::Label(label)) => {
+                declared.entry(scope.clone()).or_default().insert(label);
+            },
+            Some(VmInstruction::Goto(label)) | Some(VmInstruction::IfGoto(label)) => {
+                jumps.push((scope.clone(), label, command));
+            },
+            _ => {}
+        }
+    }
+
+    let mut diagnostics = Vec::new();
+
+    for (scope, label, command) in jumps {
+        let is_declared = declared.get(&scope).is_some_and(|labels| labels.contains(label.as_str()));
+
+        if !is_declared {
+            diagnostics.push(Diagnostic::error(command.token_span(1), TranslateError::UndefinedLabel {
+                label: label.clone(),
+                scope
+            }));
+        }
+    }
+
+    return diagnostics;
+}
+
+/// Checks that no function is declared more than once across all of the sources
+fn check_functions(commands: &[Command]) -> Vec<Diagnostic> {
+    let mut declared: HashMap<&str, Option<String>> = HashMap::new();
+    let mut diagnostics = Vec::new();
+
+    for command in commands.iter() {
+        if let Some(VmInstruction::Function { name, .. }) = command.instruction() {
+            match declared.get(name.as_str()) {
+                Some(first) => diagnostics.push(Diagnostic::error(command.token_span(1), TranslateError::DuplicateFunction {
+                    name: name.clone(),
+                    first: first.clone()
+                })),
+                None => {
+                    declared.insert(name, command.token_span(1).map(|span| span.to_string()));
+                }
+            }
+        }
+    }
+
+    return diagnostics;
+}
+
+/// Checks that every call is to a declared function
+/// 
+/// The assembler would make an undeclared function a RAM variable and jump into whatever is at
+/// that address, so these are errors for programs that are going to run
+pub(crate) fn check_calls(commands: &[Command], executable: bool) -> Vec<Diagnostic> {
+    let declared: HashSet<&str> = commands.iter().filter_map(|command| match command.instruction() {
+        Some(VmInstruction::Function { name, .. }) => Some(name.as_str()),
+        _ => None
+    }).collect();
+
+    let mut diagnostics = Vec::new();
+
+    for command in commands.iter() {
+        if let Some(VmInstruction::Call { name, .. }) = command.instruction() {
+            if !declared.contains(name.as_str()) {
+                // The bootstrap's call to the entry point is the only one not from a source file
+                let error = if command.span().is_none() {
+                    TranslateError::MissingEntryPoint(name.clone())
+                }
+                else {
+                    TranslateError::UndefinedFunction(name.clone())
+                };
+
+                diagnostics.push(if executable {
+                    Diagnostic::error(command.token_span(1), error)
+                }
+                else {
+                    Diagnostic::warning(command.token_span(1), error)
+                });
+            }
+        }
+    }
+
+    return diagnostics;
+}
+
+/// Checks that no label is declared more than once across the whole generated program
+fn check_label_collisions(commands: &[Command]) -> Vec<Diagnostic> {
+    let mut declared = HashSet::new();
+    let mut diagnostics = Vec::new();
+
+    for command in commands.iter() {
+        for line in command.get_processed().into_iter().flatten() {
+            if let Some(label) = line.strip_prefix('(').and_then(|l| l.strip_suffix(')')) {
+                if !declared.insert(label) {
+                    diagnostics.push(Diagnostic::error(
+                        command.span().cloned(),
+                        TranslateError::DuplicateLabel(label.to_owned())
+                    ));
+                }
+            }
+        }
+    }
+
+    return diagnostics;
+}
+
+/// Number of real instructions (not comments or labels) in a command's generated assembly
+fn instruction_count(command: &Command) -> usize {
+    return command.get_processed().into_iter().flatten().filter(|line| assembler::is_instruction(line)).count();
+}
+
+/// Number of ROM instructions the program takes up, broken down by function and file
+pub struct SizeReport {
+    pub total: usize,
+    /// Instructions in each function (or module, for code outside of any function), largest first
+    pub functions: Vec<(String, usize)>,
+    /// Instructions from each source file (or generated module, like the bootstrap), largest first
+    pub files: Vec<(String, usize)>
+}
+
+impl SizeReport {
+    fn new(commands: &[Command]) -> Self {
+        let mut functions: Vec<(String, usize)> = Vec::new();
+        let mut files: Vec<(String, usize)> = Vec::new();
+        let mut total = 0;
+
+        // Adds to an entry, keeping them in order of first appearance
+        let add = |entries: &mut Vec<(String, usize)>, name: &str, count: usize| {
+            match entries.iter_mut().find(|(entry, _)| entry == name) {
+                Some(entry) => entry.1 += count,
+                None        => entries.push((name.to_owned(), count))
+            }
+        };
+
+        for (command, scope) in commands.iter().zip(label_scopes(commands)) {
+            let count = instruction_count(command);
+            let file = command.span().map_or(command.program_name(), |span| span.file.as_str());
+
+            add(&mut functions, &scope, count);
+            add(&mut files, file, count);
+            total += count;
+        }
+
+        // Stable sort keeps ties in program order
+        functions.sort_by_key(|(_, count)| Reverse(*count));
+        files.sort_by_key(|(_, count)| Reverse(*count));
+
+        return SizeReport { total, functions, files };
+    }
+}
+
+impl fmt::Display for SizeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let percent = |count: usize| count as f64 * 100.0 / constants::ROM_SIZE as f64;
+
+        writeln!(f, "ROM: {} of {} instructions ({:.1}%)", self.total, constants::ROM_SIZE, percent(self.total))?;
+
+        writeln!(f, "By file:")?;
+        for (file, count) in self.files.iter() {
+            writeln!(f, "  {:>6} {:>5.1}%  {}", count, percent(*count), file)?;
+        }
+
+        writeln!(f, "By function:")?;
+        for (function, count) in self.functions.iter() {
+            writeln!(f, "  {:>6} {:>5.1}%  {}", count, percent(*count), function)?;
+        }
+
+        return Ok(());
+    }
+}
+
+/// Result of a successful translation
+pub struct Translation {
+    commands: Vec<Command>,
+    /// Warnings raised while translating
+    diagnostics: Vec<Diagnostic>,
+    /// Stack pointer set by the bootstrap code, None without bootstrap code
+    initial_sp: Option<u16>,
+    /// Commands the optimizer fused with the command after them
+    fused: HashSet<usize>
+}
+
+impl Translation {
+    /// Stack pointer set by the bootstrap code, None without bootstrap code
+    pub fn initial_sp(&self) -> Option<u16> {
+        return self.initial_sp;
+    }
+
+    /// Checks if the optimizer fused a command's code with the command after it
+    /// 
+    /// The program is not in the state the VM code would be in between the two
+    pub fn is_fused(&self, command: usize) -> bool {
+        return self.fused.contains(&command);
+    }
+
+    /// Warnings raised while translating
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        return &self.diagnostics;
+    }
+
+    /// Takes the warnings raised while translating
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        return std::mem::take(&mut self.diagnostics);
+    }
+
+    /// Translated commands, in program order
+    pub fn commands(&self) -> &[Command] {
+        return &self.commands;
+    }
+
+    /// Generated assembly, one instruction, label or comment per line
+    pub fn asm(&self) -> Vec<String> {
+        let mut asm = Vec::new();
+
+        for cmd in self.commands.iter() {
+            if let Some(processed) = cmd.get_processed() {
+                asm.extend(processed.iter().cloned());
+            }
+        }
+
+        return asm;
+    }
+
+    /// ROM addresses taken up by each command's instructions once assembled
+    pub fn rom_ranges(&self) -> Vec<Range<u16>> {
+        let mut ranges = Vec::new();
+        // Translation fails if the program doesn't fit in the ROM, so this can't overflow
+        let mut rom_addr: u16 = 0;
+
+        for cmd in self.commands.iter() {
+            let start = rom_addr;
+            rom_addr += instruction_count(cmd) as u16;
+            ranges.push(start..rom_addr);
+        }
+
+        return ranges;
+    }
+
+    /// Number of ROM instructions the program takes up, broken down by function and file
+    pub fn size_report(&self) -> SizeReport {
+        return SizeReport::new(&self.commands);
+    }
+
+    /// Listing of every command with where it came from, its ROM addresses and its instructions
+    /// 
+    /// Ex:
+    ///     Main.vm:2: push argument 0 (ROM 48-56)
+    ///          48  @0
+    ///          49  D=A
+    ///     ...
+    pub fn listing(&self) -> String {
+        let mut listing = String::new();
+
+        for (cmd, range) in self.commands.iter().zip(self.rom_ranges()) {
+            let location = match cmd.span() {
+                Some(span)  => format!("{}:{}", span.file, span.line),
+                None        => format!("<{}>", cmd.program_name())
+            };
+            let vm_code = cmd.instruction().map_or("generated code".to_owned(), |instruction| instruction.to_string());
+            let addresses = match range.len() {
+                0   => "no instructions".to_owned(),
+                1   => format!("ROM {}", range.start),
+                _   => format!("ROM {}-{}", range.start, range.end - 1)
+            };
+
+            listing.push_str(&format!("{}: {} ({})\n", location, vm_code, addresses));
+
+            let mut rom_addr = range.start;
+            for line in cmd.get_processed().into_iter().flatten() {
+                let code = assembler::strip_comment(line);
+
+                if code.is_empty() {
+                    continue;
+                }
+
+                if assembler::is_instruction(code) {
+                    listing.push_str(&format!("{:>7}  {}\n", rom_addr, code));
+                    rom_addr += 1;
+                }
+                else {
+                    listing.push_str(&format!("         {}\n", code));
+                }
+            }
+        }
+
+        return listing;
+    }
+
+    /// Generated assembly as a single newline terminated string
+    pub fn to_asm_string(&self) -> String {
+        let mut asm = String::new();
+
+        for line in self.asm() {
+            asm.push_str(&line);
+            asm.push('\n');
+        }
+
+        return asm;
+    }
+}